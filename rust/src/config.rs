@@ -2,14 +2,22 @@ use clap::{Arg, ArgAction, Command, ValueEnum};
 use std::collections::HashMap;
 use std::env;
 use std::fmt;
+use std::sync::Arc;
 
+use crate::support::auth::{Authenticator, PROTECTED_RESOURCE_PATH};
+use crate::support::jwt::{JwksSource, JwtValidator};
 use crate::types::HeadersMap;
 
+// Upper bound for --maxInFlight; initialize takes every permit at once, which
+// must fit in a u32.
+pub const MAX_IN_FLIGHT: usize = 65_536;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum OutputTransport {
     Stdio,
     Sse,
     Ws,
+    #[value(name = "streamableHttp", alias = "streamable-http")]
     StreamableHttp,
 }
 
@@ -32,18 +40,39 @@ pub struct Config {
     pub stdio: Option<String>,
     pub sse: Option<String>,
     pub streamable_http: Option<String>,
+    pub ws: Option<String>,
+    pub remote: Option<String>,
     pub output_transport: OutputTransport,
+    pub output_transports: Vec<OutputTransport>,
     pub port: u16,
     pub base_url: String,
     pub sse_path: String,
     pub message_path: String,
     pub streamable_http_path: String,
+    pub ws_path: String,
     pub log_level: LogLevel,
     pub cors: CorsConfig,
     pub health_endpoints: Vec<String>,
     pub headers: HeadersMap,
+    pub auth: Option<Arc<Authenticator>>,
     pub stateful: bool,
+    pub sse_child_per_session: bool,
+    pub ws_child_per_connection: bool,
     pub session_timeout: Option<u64>,
+    pub client_response_timeout: u64,
+    pub roots: Vec<String>,
+    pub stateless_pool_size: usize,
+    pub stateless_pool_max_uses: Option<u64>,
+    pub sampling_url: Option<String>,
+    pub sampling_models: Vec<String>,
+    pub sampling_api_key: Option<String>,
+    pub oauth2_token_url: Option<String>,
+    pub oauth2_client_id: Option<String>,
+    pub oauth2_client_secret: Option<String>,
+    pub oauth2_refresh_token: Option<String>,
+    pub oauth2_scope: Option<String>,
+    pub max_in_flight: usize,
+    pub request_timeout: u64,
     pub protocol_version: String,
     pub runtime_prompt: bool,
     pub runtime_admin_port: Option<u16>,
@@ -62,11 +91,14 @@ impl fmt::Display for ConfigError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ConfigError::MissingTransport => {
-                write!(f, "You must specify one of --stdio, --sse, or --streamableHttp")
+                write!(
+                    f,
+                    "You must specify one of --stdio, --sse, --streamableHttp, --ws, or --remote"
+                )
             }
             ConfigError::MultipleTransports => write!(
                 f,
-                "Specify only one of --stdio, --sse, or --streamableHttp"
+                "Specify only one of --stdio, --sse, --streamableHttp, --ws, or --remote"
             ),
             ConfigError::InvalidSessionTimeout(msg) => write!(f, "{msg}"),
             ConfigError::InvalidRuntimePort(msg) => write!(f, "{msg}"),
@@ -88,10 +120,14 @@ pub fn parse_config() -> Result<Config, ConfigError> {
                 .long("streamableHttp")
                 .value_name("URL"),
         )
+        .arg(Arg::new("ws").long("ws").value_name("URL"))
+        .arg(Arg::new("remote").long("remote").value_name("URL"))
         .arg(
             Arg::new("outputTransport")
                 .long("outputTransport")
                 .value_parser(clap::builder::EnumValueParser::<OutputTransport>::new())
+                .action(ArgAction::Append)
+                .value_delimiter(',')
                 .value_name("stdio|sse|ws|streamableHttp"),
         )
         .arg(
@@ -123,6 +159,7 @@ pub fn parse_config() -> Result<Config, ConfigError> {
                 .value_name("PATH")
                 .default_value("/mcp"),
         )
+        .arg(Arg::new("wsPath").long("wsPath").value_name("PATH"))
         .arg(
             Arg::new("logLevel")
                 .long("logLevel")
@@ -154,16 +191,111 @@ pub fn parse_config() -> Result<Config, ConfigError> {
                 .long("oauth2Bearer")
                 .value_name("TOKEN"),
         )
+        .arg(
+            Arg::new("oauth2TokenUrl")
+                .long("oauth2TokenUrl")
+                .value_name("URL"),
+        )
+        .arg(
+            Arg::new("oauth2ClientId")
+                .long("oauth2ClientId")
+                .value_name("ID"),
+        )
+        .arg(
+            Arg::new("oauth2ClientSecret")
+                .long("oauth2ClientSecret")
+                .value_name("SECRET"),
+        )
+        .arg(
+            Arg::new("oauth2RefreshToken")
+                .long("oauth2RefreshToken")
+                .value_name("TOKEN"),
+        )
+        .arg(
+            Arg::new("oauth2Scope")
+                .long("oauth2Scope")
+                .value_name("SCOPE"),
+        )
+        .arg(Arg::new("authFile").long("authFile").value_name("PATH"))
+        .arg(Arg::new("jwksFile").long("jwksFile").value_name("PATH"))
+        .arg(Arg::new("jwksUrl").long("jwksUrl").value_name("URL"))
+        .arg(Arg::new("jwtIssuer").long("jwtIssuer").value_name("ISSUER"))
+        .arg(Arg::new("jwtAudience").long("jwtAudience").value_name("AUDIENCE"))
+        .arg(
+            Arg::new("requiredScope")
+                .long("requiredScope")
+                .action(ArgAction::Append)
+                .value_name("SCOPE"),
+        )
+        .arg(
+            Arg::new("authorizationServer")
+                .long("authorizationServer")
+                .action(ArgAction::Append)
+                .value_name("URL"),
+        )
         .arg(
             Arg::new("stateful")
                 .long("stateful")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("sseChildPerSession")
+                .long("sseChildPerSession")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("wsChildPerConnection")
+                .long("wsChildPerConnection")
+                .action(ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("sessionTimeout")
                 .long("sessionTimeout")
                 .value_name("MILLISECONDS"),
         )
+        .arg(
+            Arg::new("clientResponseTimeout")
+                .long("clientResponseTimeout")
+                .value_name("MILLISECONDS"),
+        )
+        .arg(
+            Arg::new("root")
+                .long("root")
+                .action(ArgAction::Append)
+                .value_name("PATH_OR_URI"),
+        )
+        .arg(
+            Arg::new("statelessPoolSize")
+                .long("statelessPoolSize")
+                .value_name("COUNT"),
+        )
+        .arg(
+            Arg::new("statelessPoolMaxUses")
+                .long("statelessPoolMaxUses")
+                .value_name("COUNT"),
+        )
+        .arg(
+            Arg::new("maxInFlight")
+                .long("maxInFlight")
+                .value_name("COUNT"),
+        )
+        .arg(
+            Arg::new("requestTimeout")
+                .long("requestTimeout")
+                .value_name("MILLISECONDS"),
+        )
+        .arg(Arg::new("samplingUrl").long("samplingUrl").value_name("URL"))
+        .arg(
+            Arg::new("samplingModel")
+                .long("samplingModel")
+                .action(ArgAction::Append)
+                .value_name("MODEL"),
+        )
+        .arg(
+            Arg::new("samplingApiKey")
+                .long("samplingApiKey")
+                .value_name("KEY"),
+        )
         .arg(
             Arg::new("protocolVersion")
                 .long("protocolVersion")
@@ -186,8 +318,16 @@ pub fn parse_config() -> Result<Config, ConfigError> {
     let streamable_http = matches
         .get_one::<String>("streamableHttp")
         .cloned();
+    let ws = matches.get_one::<String>("ws").cloned();
+    let remote = matches.get_one::<String>("remote").cloned();
 
-    let active = [stdio.is_some(), sse.is_some(), streamable_http.is_some()]
+    let active = [
+        stdio.is_some(),
+        sse.is_some(),
+        streamable_http.is_some(),
+        ws.is_some(),
+        remote.is_some(),
+    ]
         .iter()
         .filter(|v| **v)
         .count();
@@ -198,15 +338,26 @@ pub fn parse_config() -> Result<Config, ConfigError> {
         return Err(ConfigError::MultipleTransports);
     }
 
-    let output_transport = matches
-        .get_one::<OutputTransport>("outputTransport")
-        .copied()
-        .or(default_output)
-        .ok_or_else(|| {
-            ConfigError::InvalidArg(
-                "outputTransport must be specified or inferable from input transport".into(),
-            )
-        })?;
+    let mut output_transports: Vec<OutputTransport> = Vec::new();
+    for output in matches
+        .get_many::<OutputTransport>("outputTransport")
+        .map(|vals| vals.copied().collect())
+        .unwrap_or_else(|| default_output.into_iter().collect::<Vec<_>>())
+    {
+        if !output_transports.contains(&output) {
+            output_transports.push(output);
+        }
+    }
+    let output_transport = output_transports.first().copied().ok_or_else(|| {
+        ConfigError::InvalidArg(
+            "outputTransport must be specified or inferable from input transport".into(),
+        )
+    })?;
+    if output_transports.len() > 1 && output_transports.contains(&OutputTransport::Stdio) {
+        return Err(ConfigError::InvalidArg(
+            "outputTransport stdio cannot be combined with other outputs".into(),
+        ));
+    }
 
     let port = matches
         .get_one::<String>("port")
@@ -230,6 +381,35 @@ pub fn parse_config() -> Result<Config, ConfigError> {
         .get_one::<String>("streamableHttpPath")
         .cloned()
         .unwrap_or_else(|| "/mcp".to_string());
+    // WS shares --messagePath unless it is mounted next to SSE, which owns it.
+    let ws_path = matches
+        .get_one::<String>("wsPath")
+        .cloned()
+        .unwrap_or_else(|| {
+            if output_transports.contains(&OutputTransport::Sse) {
+                "/ws".to_string()
+            } else {
+                message_path.clone()
+            }
+        });
+    if output_transports.len() > 1 {
+        let mut paths: Vec<&str> = Vec::new();
+        for output in &output_transports {
+            match output {
+                OutputTransport::Sse => paths.extend([sse_path.as_str(), message_path.as_str()]),
+                OutputTransport::StreamableHttp => paths.push(&streamable_http_path),
+                OutputTransport::Ws => paths.push(&ws_path),
+                OutputTransport::Stdio => {}
+            }
+        }
+        for (i, path) in paths.iter().enumerate() {
+            if paths[..i].contains(path) {
+                return Err(ConfigError::InvalidArg(format!(
+                    "Combined outputs cannot share the path {path}"
+                )));
+            }
+        }
+    }
     let log_level = matches
         .get_one::<LogLevel>("logLevel")
         .copied()
@@ -250,6 +430,8 @@ pub fn parse_config() -> Result<Config, ConfigError> {
     let oauth2_bearer = matches.get_one::<String>("oauth2Bearer").cloned();
     let headers = parse_headers(&header_values, oauth2_bearer.as_deref())?;
 
+    let auth = parse_auth(&matches, &base_url)?;
+
     let cors = if cors_input.present {
         if cors_input.allow_all {
             CorsConfig::AllowAll
@@ -265,6 +447,8 @@ pub fn parse_config() -> Result<Config, ConfigError> {
     };
 
     let stateful = matches.get_flag("stateful");
+    let sse_child_per_session = matches.get_flag("sseChildPerSession");
+    let ws_child_per_connection = matches.get_flag("wsChildPerConnection");
     let session_timeout = if let Some(raw) = matches.get_one::<String>("sessionTimeout") {
         let val: i64 = raw.parse().map_err(|_| {
             ConfigError::InvalidSessionTimeout(format!(
@@ -281,6 +465,147 @@ pub fn parse_config() -> Result<Config, ConfigError> {
         None
     };
 
+    let client_response_timeout =
+        if let Some(raw) = matches.get_one::<String>("clientResponseTimeout") {
+            let val: u64 = raw.parse().map_err(|_| {
+                ConfigError::InvalidArg(format!(
+                    "clientResponseTimeout must be a positive number, received: {raw}"
+                ))
+            })?;
+            if val == 0 {
+                return Err(ConfigError::InvalidArg(format!(
+                    "clientResponseTimeout must be a positive number, received: {raw}"
+                )));
+            }
+            val
+        } else {
+            60_000
+        };
+
+    let roots: Vec<String> = matches
+        .get_many::<String>("root")
+        .map(|vals| vals.map(|v| v.to_string()).collect())
+        .unwrap_or_default();
+
+    let stateless_pool_size = if let Some(raw) = matches.get_one::<String>("statelessPoolSize") {
+        raw.parse::<usize>().map_err(|_| {
+            ConfigError::InvalidArg(format!(
+                "statelessPoolSize must be a non-negative number, received: {raw}"
+            ))
+        })?
+    } else {
+        0
+    };
+    let stateless_pool_max_uses =
+        if let Some(raw) = matches.get_one::<String>("statelessPoolMaxUses") {
+            let val: u64 = raw.parse().map_err(|_| {
+                ConfigError::InvalidArg(format!(
+                    "statelessPoolMaxUses must be a positive number, received: {raw}"
+                ))
+            })?;
+            if val == 0 {
+                return Err(ConfigError::InvalidArg(format!(
+                    "statelessPoolMaxUses must be a positive number, received: {raw}"
+                )));
+            }
+            Some(val)
+        } else {
+            None
+        };
+
+    let max_in_flight = if let Some(raw) = matches.get_one::<String>("maxInFlight") {
+        let val: usize = raw.parse().map_err(|_| {
+            ConfigError::InvalidArg(format!(
+                "maxInFlight must be a positive number, received: {raw}"
+            ))
+        })?;
+        if val == 0 {
+            return Err(ConfigError::InvalidArg(format!(
+                "maxInFlight must be a positive number, received: {raw}"
+            )));
+        }
+        if val > MAX_IN_FLIGHT {
+            return Err(ConfigError::InvalidArg(format!(
+                "maxInFlight must be at most {MAX_IN_FLIGHT}, received: {raw}"
+            )));
+        }
+        val
+    } else {
+        32
+    };
+
+    let request_timeout = if let Some(raw) = matches.get_one::<String>("requestTimeout") {
+        let val: u64 = raw.parse().map_err(|_| {
+            ConfigError::InvalidArg(format!(
+                "requestTimeout must be a positive number, received: {raw}"
+            ))
+        })?;
+        if val == 0 {
+            return Err(ConfigError::InvalidArg(format!(
+                "requestTimeout must be a positive number, received: {raw}"
+            )));
+        }
+        val
+    } else {
+        300_000
+    };
+
+    let sampling_url = matches.get_one::<String>("samplingUrl").cloned();
+    if let Some(raw) = &sampling_url {
+        reqwest::Url::parse(raw).map_err(|_| {
+            ConfigError::InvalidArg(format!("samplingUrl must be a valid URL, received: {raw}"))
+        })?;
+    }
+    let sampling_models: Vec<String> = matches
+        .get_many::<String>("samplingModel")
+        .map(|vals| vals.map(|v| v.to_string()).collect())
+        .unwrap_or_default();
+    let sampling_api_key = matches
+        .get_one::<String>("samplingApiKey")
+        .cloned()
+        .or_else(|| env::var("SAMPLING_API_KEY").ok());
+
+    let oauth2_token_url = matches.get_one::<String>("oauth2TokenUrl").cloned();
+    let oauth2_client_id = matches.get_one::<String>("oauth2ClientId").cloned();
+    let oauth2_client_secret = matches
+        .get_one::<String>("oauth2ClientSecret")
+        .cloned()
+        .or_else(|| env::var("OAUTH2_CLIENT_SECRET").ok());
+    let oauth2_refresh_token = matches
+        .get_one::<String>("oauth2RefreshToken")
+        .cloned()
+        .or_else(|| env::var("OAUTH2_REFRESH_TOKEN").ok());
+    let oauth2_scope = matches.get_one::<String>("oauth2Scope").cloned();
+    if let Some(raw) = &oauth2_token_url {
+        reqwest::Url::parse(raw).map_err(|_| {
+            ConfigError::InvalidArg(format!("oauth2TokenUrl must be a valid URL, received: {raw}"))
+        })?;
+        if oauth2_client_id.is_none() {
+            return Err(ConfigError::InvalidArg(
+                "oauth2TokenUrl requires --oauth2ClientId".into(),
+            ));
+        }
+        if oauth2_client_secret.is_none() && oauth2_refresh_token.is_none() {
+            return Err(ConfigError::InvalidArg(
+                "oauth2TokenUrl requires --oauth2ClientSecret or --oauth2RefreshToken".into(),
+            ));
+        }
+        if oauth2_bearer.is_some() {
+            return Err(ConfigError::InvalidArg(
+                "oauth2TokenUrl cannot be combined with --oauth2Bearer".into(),
+            ));
+        }
+        // Refreshed tokens reach the upstream through the stdio output's
+        // runtime update loop.
+        let remote_input = sse.is_some() || streamable_http.is_some() || remote.is_some();
+        if !remote_input || output_transport != OutputTransport::Stdio {
+            return Err(ConfigError::InvalidArg(
+                "oauth2TokenUrl requires an --sse, --streamableHttp or --remote input with stdio output"
+                    .into(),
+            ));
+        }
+    }
+
     let protocol_version = matches
         .get_one::<String>("protocolVersion")
         .cloned()
@@ -307,18 +632,39 @@ pub fn parse_config() -> Result<Config, ConfigError> {
         stdio,
         sse,
         streamable_http,
+        ws,
+        remote,
         output_transport,
+        output_transports,
         port,
         base_url,
         sse_path,
         message_path,
         streamable_http_path,
+        ws_path,
         log_level,
         cors,
         health_endpoints,
         headers,
+        auth,
         stateful,
+        sse_child_per_session,
+        ws_child_per_connection,
         session_timeout,
+        client_response_timeout,
+        roots,
+        stateless_pool_size,
+        stateless_pool_max_uses,
+        sampling_url,
+        sampling_models,
+        sampling_api_key,
+        oauth2_token_url,
+        oauth2_client_id,
+        oauth2_client_secret,
+        oauth2_refresh_token,
+        oauth2_scope,
+        max_in_flight,
+        request_timeout,
         protocol_version,
         runtime_prompt,
         runtime_admin_port,
@@ -335,6 +681,12 @@ fn default_output_transport(args: &[String]) -> Option<OutputTransport> {
     if args.iter().any(|arg| arg == "--streamableHttp") {
         return Some(OutputTransport::Stdio);
     }
+    if args.iter().any(|arg| arg == "--ws") {
+        return Some(OutputTransport::Stdio);
+    }
+    if args.iter().any(|arg| arg == "--remote") {
+        return Some(OutputTransport::Stdio);
+    }
     None
 }
 
@@ -372,6 +724,73 @@ fn parse_cors_flags(args: &[String]) -> CorsInput {
     input
 }
 
+fn parse_auth(
+    matches: &clap::ArgMatches,
+    base_url: &str,
+) -> Result<Option<Arc<Authenticator>>, ConfigError> {
+    let auth_file = matches.get_one::<String>("authFile");
+    let jwks = match (
+        matches.get_one::<String>("jwksFile"),
+        matches.get_one::<String>("jwksUrl"),
+    ) {
+        (Some(_), Some(_)) => {
+            return Err(ConfigError::InvalidArg(
+                "Specify only one of --jwksFile or --jwksUrl".into(),
+            ))
+        }
+        (Some(path), None) => Some(JwksSource::File(path.clone())),
+        (None, Some(url)) => {
+            reqwest::Url::parse(url).map_err(|_| {
+                ConfigError::InvalidArg(format!("jwksUrl must be a valid URL, received: {url}"))
+            })?;
+            Some(JwksSource::Url(url.clone()))
+        }
+        (None, None) => None,
+    };
+    if auth_file.is_none() && jwks.is_none() {
+        return Ok(None);
+    }
+
+    let mut auth =
+        Authenticator::new(auth_file.map(String::as_str)).map_err(ConfigError::InvalidArg)?;
+    if let Some(jwks) = jwks {
+        let (Some(issuer), Some(audience)) = (
+            matches.get_one::<String>("jwtIssuer"),
+            matches.get_one::<String>("jwtAudience"),
+        ) else {
+            return Err(ConfigError::InvalidArg(
+                "JWT validation requires --jwtIssuer and --jwtAudience".into(),
+            ));
+        };
+        let scopes: Vec<String> = matches
+            .get_many::<String>("requiredScope")
+            .map(|vals| vals.cloned().collect())
+            .unwrap_or_default();
+        let authorization_servers: Vec<String> = matches
+            .get_many::<String>("authorizationServer")
+            .map(|vals| vals.cloned().collect())
+            .unwrap_or_else(|| vec![issuer.clone()]);
+        // Clients discover the metadata from the 401 challenge, so it needs
+        // an absolute URL: --baseUrl, or the audience when it is one.
+        let metadata_url = if !base_url.is_empty() {
+            Some(format!(
+                "{}{PROTECTED_RESOURCE_PATH}",
+                base_url.trim_end_matches('/')
+            ))
+        } else {
+            reqwest::Url::parse(audience)
+                .ok()
+                .filter(|url| matches!(url.scheme(), "http" | "https"))
+                .and_then(|url| url.join(PROTECTED_RESOURCE_PATH).ok())
+                .map(|url| url.to_string())
+        };
+        let jwt = JwtValidator::new(jwks, issuer.clone(), audience.clone(), scopes)
+            .map_err(ConfigError::InvalidArg)?;
+        auth = auth.with_jwt(jwt, authorization_servers, metadata_url);
+    }
+    Ok(Some(Arc::new(auth)))
+}
+
 fn parse_headers(
     header_values: &[String],
     oauth2_bearer: Option<&str>,
@@ -1,5 +0,0 @@
-pub mod stdio_to_sse;
-pub mod stdio_to_ws;
-pub mod stdio_to_streamable_http;
-pub mod sse_to_stdio;
-pub mod streamable_http_to_stdio;
@@ -1,7 +1,7 @@
 mod config;
-mod gateways;
 mod support;
 mod runtime;
+mod transport;
 mod types;
 
 use std::sync::Arc;
@@ -10,15 +10,13 @@ use tokio::sync::{mpsc, oneshot};
 use futures::future::BoxFuture;
 
 use crate::config::{parse_config, OutputTransport};
-use crate::gateways::{
-    sse_to_stdio, stdio_to_sse, stdio_to_streamable_http, stdio_to_ws,
-    streamable_http_to_stdio,
-};
+use crate::support::oauth_client::OAuthClient;
 use crate::support::telemetry::init_telemetry;
 use crate::runtime::{RuntimeApplyResult, RuntimeUpdate, RuntimeUpdateRequest};
 use crate::runtime::admin::spawn_admin_server;
 use crate::runtime::prompt::spawn_prompt;
 use crate::runtime::store::RuntimeArgsStore;
+use crate::transport::{build_sink, SourceSpec};
 use crate::types::RuntimeArgs;
 
 #[tokio::main]
@@ -93,34 +91,26 @@ async fn main() {
             }) as BoxFuture<'static, RuntimeApplyResult>
         });
         let runtime_clone = runtime_store.clone();
+        let auth = config.auth.clone();
         tokio::spawn(async move {
-            spawn_admin_server(addr, runtime_clone, handler).await;
+            spawn_admin_server(addr, runtime_clone, handler, auth).await;
         });
     }
 
-    let result = if config.stdio.is_some() {
-        match config.output_transport {
-            OutputTransport::Sse => stdio_to_sse::run(config, runtime_store, update_rx).await,
-            OutputTransport::Ws => stdio_to_ws::run(config, runtime_store, update_rx).await,
-            OutputTransport::StreamableHttp => {
-                stdio_to_streamable_http::run(config, runtime_store, update_rx).await
-            }
-            OutputTransport::Stdio => Err("stdio→stdio is not supported".to_string()),
-        }
-    } else if config.sse.is_some() {
-        match config.output_transport {
-            OutputTransport::Stdio => sse_to_stdio::run(config, runtime_store, update_rx).await,
-            _ => Err("sse→output transport not supported".to_string()),
+    let oauth = OAuthClient::from_config(&config);
+    if let Some(oauth) = &oauth {
+        if let Err(err) = oauth.start(runtime_store.clone(), update_tx.clone()).await {
+            tracing::error!("Fatal error: Failed to obtain OAuth access token: {err}");
+            std::process::exit(1);
         }
-    } else if config.streamable_http.is_some() {
-        match config.output_transport {
-            OutputTransport::Stdio => {
-                streamable_http_to_stdio::run(config, runtime_store, update_rx).await
-            }
-            _ => Err("streamableHttp→output transport not supported".to_string()),
+    }
+
+    let result = match SourceSpec::from_config(&config, oauth) {
+        Ok(SourceSpec::Stdio(_)) if config.output_transport == OutputTransport::Stdio => {
+            Err("stdio→stdio is not supported".to_string())
         }
-    } else {
-        Err("Invalid input transport".to_string())
+        Ok(source) => build_sink(config).serve(source, runtime_store, update_rx).await,
+        Err(err) => Err(err),
     };
 
     if let Err(err) = result {
@@ -10,6 +10,7 @@ use axum::{Json, Router};
 use futures::future::BoxFuture;
 use crate::runtime::{RuntimeApplyResult, RuntimeScope, RuntimeUpdate};
 use crate::runtime::store::{RuntimeArgsStore, RuntimeArgsUpdate};
+use crate::support::auth::{require_auth, Authenticator};
 
 #[derive(Clone)]
 pub struct AdminState {
@@ -21,15 +22,19 @@ pub async fn spawn_admin_server(
     addr: SocketAddr,
     runtime: RuntimeArgsStore,
     handler: Arc<dyn Fn(RuntimeUpdate) -> BoxFuture<'static, RuntimeApplyResult> + Send + Sync>,
+    auth: Option<Arc<Authenticator>>,
 ) {
     let state = AdminState { runtime, handler };
 
-    let router = Router::new()
+    let mut router = Router::new()
         .route("/runtime/defaults", post(update_defaults))
         .route("/runtime/session/:id", post(update_session))
         .route("/runtime/sessions", get(list_sessions))
-        .with_state(state)
-        .layer(middleware::from_fn(only_loopback));
+        .with_state(state);
+    if let Some(auth) = auth {
+        router = router.layer(middleware::from_fn_with_state(auth, require_auth));
+    }
+    let router = router.layer(middleware::from_fn(only_loopback));
 
     tracing::info!("Runtime admin endpoint listening on http://{addr}");
 
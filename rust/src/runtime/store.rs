@@ -20,6 +20,14 @@ pub struct UpdateResult {
     pub headers_changed: bool,
 }
 
+impl UpdateResult {
+    // A remote source takes header changes through `restart` too, which only
+    // swaps the headers it sends upstream.
+    pub fn restarts(&self, remote: bool) -> bool {
+        self.restart_needed || (remote && self.headers_changed)
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct RuntimeArgsStore {
     global: Arc<ArcSwap<RuntimeArgs>>,
@@ -83,6 +91,16 @@ impl RuntimeArgsStore {
         global.as_ref().clone()
     }
 
+    pub async fn register_session(&self, session_id: &str) {
+        let mut sessions = self.sessions.write().await;
+        sessions.entry(session_id.to_string()).or_default();
+    }
+
+    pub async fn remove_session(&self, session_id: &str) {
+        let mut sessions = self.sessions.write().await;
+        sessions.remove(session_id);
+    }
+
     pub async fn list_sessions(&self) -> Vec<String> {
         let sessions = self.sessions.read().await;
         sessions.keys().cloned().collect()
@@ -0,0 +1,389 @@
+use std::fmt;
+use std::sync::Arc;
+
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::support::jwt::{JwtError, JwtValidator};
+
+const REALM: &str = "supergateway";
+
+// Who made a request, as established by `require_auth`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    pub name: String,
+}
+
+impl fmt::Display for Principal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+pub const PROTECTED_RESOURCE_PATH: &str = "/.well-known/oauth-protected-resource";
+
+// Static bearer tokens / API keys, one `<principal> <token>` pair per line,
+// and/or JWT access tokens when acting as an OAuth resource server.
+pub struct Authenticator {
+    credentials: Vec<(String, String)>,
+    jwt: Option<JwtValidator>,
+    authorization_servers: Vec<String>,
+    metadata_url: Option<String>,
+}
+
+impl fmt::Display for Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if !self.credentials.is_empty() {
+            parts.push(format!("{} static credential(s)", self.credentials.len()));
+        }
+        if let Some(jwt) = &self.jwt {
+            parts.push(format!("JWT issued by {} for {}", jwt.issuer, jwt.audience));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+impl fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Authenticator({self})")
+    }
+}
+
+enum AuthError {
+    Missing,
+    Invalid,
+    InsufficientScope,
+}
+
+impl Authenticator {
+    pub fn new(credentials_file: Option<&str>) -> Result<Self, String> {
+        let credentials = match credentials_file {
+            Some(path) => load_credentials(path)?,
+            None => Vec::new(),
+        };
+        Ok(Self {
+            credentials,
+            jwt: None,
+            authorization_servers: Vec::new(),
+            metadata_url: None,
+        })
+    }
+
+    pub fn with_jwt(
+        mut self,
+        jwt: JwtValidator,
+        authorization_servers: Vec<String>,
+        metadata_url: Option<String>,
+    ) -> Self {
+        self.jwt = Some(jwt);
+        self.authorization_servers = authorization_servers;
+        self.metadata_url = metadata_url;
+        self
+    }
+
+    // RFC 9728 metadata, served only in resource-server mode.
+    pub fn resource_metadata(&self) -> Option<serde_json::Value> {
+        let jwt = self.jwt.as_ref()?;
+        let mut metadata = serde_json::json!({
+            "resource": jwt.audience,
+            "authorization_servers": self.authorization_servers,
+            "bearer_methods_supported": ["header"],
+        });
+        if !jwt.scopes.is_empty() {
+            metadata["scopes_supported"] = serde_json::json!(jwt.scopes);
+        }
+        Some(metadata)
+    }
+
+    async fn authenticate(&self, headers: &HeaderMap) -> Result<Principal, AuthError> {
+        let token = presented_token(headers).ok_or(AuthError::Missing)?;
+        // Compare against every credential so timing does not reveal which matched.
+        let mut matched = None;
+        for (name, expected) in &self.credentials {
+            if constant_time_eq(token.as_bytes(), expected.as_bytes()) && matched.is_none() {
+                matched = Some(name);
+            }
+        }
+        if let Some(name) = matched {
+            return Ok(Principal { name: name.clone() });
+        }
+        let Some(jwt) = &self.jwt else {
+            return Err(AuthError::Invalid);
+        };
+        jwt.validate(token).await.map_err(|err| match err {
+            JwtError::Invalid(reason) => {
+                tracing::debug!("Rejected access token: {reason}");
+                AuthError::Invalid
+            }
+            JwtError::InsufficientScope => AuthError::InsufficientScope,
+        })
+    }
+
+    fn challenge(&self, err: &AuthError) -> String {
+        let mut params = vec![format!("realm=\"{REALM}\"")];
+        if let Some(url) = &self.metadata_url {
+            params.push(format!("resource_metadata=\"{url}\""));
+        }
+        match err {
+            AuthError::Missing => {}
+            AuthError::Invalid => params.push("error=\"invalid_token\"".to_string()),
+            AuthError::InsufficientScope => {
+                params.push("error=\"insufficient_scope\"".to_string());
+                if let Some(jwt) = &self.jwt {
+                    params.push(format!("scope=\"{}\"", jwt.scopes.join(" ")));
+                }
+            }
+        }
+        format!("Bearer {}", params.join(", "))
+    }
+}
+
+fn load_credentials(path: &str) -> Result<Vec<(String, String)>, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read auth file {path}: {err}"))?;
+    let mut credentials = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let (Some(name), Some(token), None) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(format!(
+                "Invalid auth file line {}: expected `<principal> <token>`",
+                index + 1
+            ));
+        };
+        if credentials.iter().any(|(_, existing)| existing == token) {
+            return Err(format!("Invalid auth file line {}: duplicate token", index + 1));
+        }
+        credentials.push((name.to_string(), token.to_string()));
+    }
+    if credentials.is_empty() {
+        return Err(format!("Auth file {path} has no credentials"));
+    }
+    Ok(credentials)
+}
+
+// Rejects unauthenticated requests and hands the principal to handlers as a
+// request extension.
+pub async fn require_auth(
+    State(auth): State<Arc<Authenticator>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    match auth.authenticate(req.headers()).await {
+        Ok(principal) => {
+            req.extensions_mut().insert(principal);
+            next.run(req).await
+        }
+        Err(err) => {
+            let (status, body) = match err {
+                AuthError::Missing => (StatusCode::UNAUTHORIZED, "Missing credentials"),
+                AuthError::Invalid => (StatusCode::UNAUTHORIZED, "Invalid credentials"),
+                AuthError::InsufficientScope => (StatusCode::FORBIDDEN, "Insufficient scope"),
+            };
+            let mut response = (status, body).into_response();
+            if let Ok(value) = HeaderValue::from_str(&auth.challenge(&err)) {
+                response
+                    .headers_mut()
+                    .insert(axum::http::header::WWW_AUTHENTICATE, value);
+            }
+            response
+        }
+    }
+}
+
+fn presented_token(headers: &HeaderMap) -> Option<&str> {
+    if let Some(value) = headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+    {
+        let (scheme, token) = value.split_once(' ')?;
+        return scheme
+            .eq_ignore_ascii_case("bearer")
+            .then(|| token.trim())
+            .filter(|token| !token.is_empty());
+    }
+    headers
+        .get("X-API-Key")
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::middleware;
+    use axum::routing::get;
+    use axum::{Extension, Router};
+    use jsonwebtoken::{encode, get_current_timestamp, Algorithm, EncodingKey, Header};
+    use serde_json::json;
+
+    use super::*;
+    use crate::support::jwt::JwksSource;
+
+    const RSA_KEY: &str = include_str!("../../tests/fixtures/jwt/rsa.pem");
+
+    fn credentials_file(contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("auth-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn headers(name: &str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            axum::http::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+            HeaderValue::from_str(value).unwrap(),
+        );
+        headers
+    }
+
+    fn authenticator() -> Authenticator {
+        let jwks = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/jwt/jwks.json");
+        let jwt = JwtValidator::new(
+            JwksSource::File(jwks.to_string()),
+            "https://issuer.test".to_string(),
+            "https://gateway.test".to_string(),
+            vec!["mcp:admin".to_string()],
+        )
+        .unwrap();
+        Authenticator::new(Some(&credentials_file("alice secret-a\n")))
+            .unwrap()
+            .with_jwt(jwt, vec!["https://issuer.test".to_string()], None)
+    }
+
+    fn read_only_token() -> String {
+        let now = get_current_timestamp();
+        let claims = json!({
+            "iss": "https://issuer.test",
+            "aud": "https://gateway.test",
+            "sub": "bob",
+            "iat": now,
+            "exp": now + 600,
+            "scope": "mcp:read",
+        });
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some("test-rsa".to_string());
+        let key = EncodingKey::from_rsa_pem(RSA_KEY.as_bytes()).unwrap();
+        encode(&header, &claims, &key).unwrap()
+    }
+
+    async fn serve() -> String {
+        let app = Router::new()
+            .route("/", get(|Extension(principal): Extension<Principal>| async move {
+                principal.name
+            }))
+            .layer(middleware::from_fn_with_state(Arc::new(authenticator()), require_auth));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{addr}")
+    }
+
+    fn challenge(response: &reqwest::Response) -> &str {
+        response.headers()["www-authenticate"].to_str().unwrap()
+    }
+
+    #[test]
+    fn loads_credentials_skipping_comments() {
+        let path = credentials_file("# principals\nalice secret-a\n\nbob secret-b\n");
+        let credentials = load_credentials(&path).unwrap();
+        assert_eq!(
+            credentials,
+            vec![
+                ("alice".to_string(), "secret-a".to_string()),
+                ("bob".to_string(), "secret-b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_credentials() {
+        let malformed = load_credentials(&credentials_file("alice secret-a\nbob\n")).unwrap_err();
+        assert!(malformed.contains("line 2"));
+        let extra = load_credentials(&credentials_file("alice secret-a extra\n")).unwrap_err();
+        assert!(extra.contains("line 1"));
+        let duplicate =
+            load_credentials(&credentials_file("alice secret\nbob secret\n")).unwrap_err();
+        assert!(duplicate.contains("duplicate token"));
+        let empty = load_credentials(&credentials_file("# nobody\n\n")).unwrap_err();
+        assert!(empty.contains("no credentials"));
+    }
+
+    #[test]
+    fn reads_bearer_token_and_api_key() {
+        let bearer = headers("authorization", "Bearer  secret-a ");
+        assert_eq!(presented_token(&bearer), Some("secret-a"));
+        let lowercase = headers("authorization", "bearer secret-a");
+        assert_eq!(presented_token(&lowercase), Some("secret-a"));
+        assert_eq!(presented_token(&headers("authorization", "Basic secret-a")), None);
+        assert_eq!(presented_token(&headers("x-api-key", " secret-a ")), Some("secret-a"));
+        assert_eq!(presented_token(&headers("x-api-key", "")), None);
+        assert_eq!(presented_token(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn compares_in_constant_time() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret-a"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[tokio::test]
+    async fn admits_known_credentials() {
+        let base = serve().await;
+        let http = reqwest::Client::new();
+        let bearer = http.get(&base).bearer_auth("secret-a").send().await.unwrap();
+        assert_eq!(bearer.status(), 200);
+        assert_eq!(bearer.text().await.unwrap(), "alice");
+        let api_key = http.get(&base).header("X-API-Key", "secret-a").send().await.unwrap();
+        assert_eq!(api_key.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn challenges_missing_and_invalid_credentials() {
+        let base = serve().await;
+        let http = reqwest::Client::new();
+
+        let missing = http.get(&base).send().await.unwrap();
+        assert_eq!(missing.status(), 401);
+        assert_eq!(challenge(&missing), "Bearer realm=\"supergateway\"");
+
+        let invalid = http.get(&base).bearer_auth("unknown").send().await.unwrap();
+        assert_eq!(invalid.status(), 401);
+        assert_eq!(
+            challenge(&invalid),
+            "Bearer realm=\"supergateway\", error=\"invalid_token\""
+        );
+    }
+
+    #[tokio::test]
+    async fn forbids_insufficient_scope() {
+        let base = serve().await;
+        let response = reqwest::Client::new()
+            .get(&base)
+            .bearer_auth(read_only_token())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+        assert_eq!(
+            challenge(&response),
+            "Bearer realm=\"supergateway\", error=\"insufficient_scope\", scope=\"mcp:admin\""
+        );
+    }
+}
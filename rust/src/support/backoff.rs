@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use uuid::Uuid;
+
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+// Failed attempts (about half a minute of retrying) after which messages held
+// for the connection are failed instead of kept waiting.
+const GIVE_UP_ATTEMPTS: u32 = 6;
+
+// Exponential reconnect delay with up to 50% random jitter so that many
+// clients dropped at once do not reconnect in lockstep.
+#[derive(Default)]
+pub struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = BASE_DELAY
+            .saturating_mul(1 << self.attempt.min(16))
+            .min(MAX_DELAY);
+        self.attempt = self.attempt.saturating_add(1);
+        let jitter = (Uuid::new_v4().as_u128() % 1000) as u32;
+        delay + delay / 2 * jitter / 1000
+    }
+
+    pub fn gave_up(&self) -> bool {
+        self.attempt >= GIVE_UP_ATTEMPTS
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+
+pub enum Collected {
+    Pass(serde_json::Value),
+    Held,
+    Complete(serde_json::Value),
+    // A response for a batch that was already completed with an error for it.
+    Late,
+}
+
+struct PendingBatch {
+    ids: Vec<serde_json::Value>,
+    responses: Vec<serde_json::Value>,
+    since: Instant,
+}
+
+#[derive(Default)]
+struct Batches {
+    pending: HashMap<String, Vec<PendingBatch>>,
+    abandoned: HashMap<String, Vec<(serde_json::Value, Instant)>>,
+}
+
+#[derive(Default)]
+pub struct BatchCollector {
+    batches: Mutex<Batches>,
+}
+
+impl BatchCollector {
+    pub async fn register(&self, key: &str, ids: Vec<serde_json::Value>) {
+        if ids.is_empty() {
+            return;
+        }
+        let mut batches = self.batches.lock().await;
+        batches.pending.entry(key.to_string()).or_default().push(PendingBatch {
+            ids,
+            responses: Vec::new(),
+            since: Instant::now(),
+        });
+    }
+
+    // Holds responses that belong to a registered batch until every id in it
+    // has been answered, then releases them as a single array.
+    pub async fn collect(&self, key: &str, message: serde_json::Value) -> Collected {
+        if message.get("method").is_some() {
+            return Collected::Pass(message);
+        }
+        let Some(id) = message.get("id").cloned() else {
+            return Collected::Pass(message);
+        };
+        let mut batches = self.batches.lock().await;
+        if let Some(abandoned) = batches.abandoned.get_mut(key) {
+            if let Some(index) = abandoned.iter().position(|(late, _)| *late == id) {
+                abandoned.swap_remove(index);
+                return Collected::Late;
+            }
+        }
+        let Some(pending) = batches.pending.get_mut(key) else {
+            return Collected::Pass(message);
+        };
+        let Some(index) = pending.iter().position(|batch| batch.ids.contains(&id)) else {
+            return Collected::Pass(message);
+        };
+        let batch = &mut pending[index];
+        batch.ids.retain(|pending_id| *pending_id != id);
+        batch.responses.push(message);
+        if !batch.ids.is_empty() {
+            return Collected::Held;
+        }
+        let batch = pending.remove(index);
+        if pending.is_empty() {
+            batches.pending.remove(key);
+        }
+        Collected::Complete(serde_json::Value::Array(batch.responses))
+    }
+
+    // Completes batches still waiting after `max_age` with an error for every
+    // missing id, so the client is not left without a reply. Responses that
+    // turn up afterwards are collected as `Late`.
+    pub async fn expire(&self, max_age: Duration) -> Vec<(String, serde_json::Value)> {
+        let mut batches = self.batches.lock().await;
+        let Batches { pending, abandoned } = &mut *batches;
+        for late in abandoned.values_mut() {
+            late.retain(|(_, since)| since.elapsed() < max_age);
+        }
+        abandoned.retain(|_, late| !late.is_empty());
+        let mut expired = Vec::new();
+        for (key, waiting) in pending.iter_mut() {
+            let (stale, fresh) = std::mem::take(waiting)
+                .into_iter()
+                .partition(|batch| batch.since.elapsed() >= max_age);
+            *waiting = fresh;
+            for mut batch in stale {
+                for id in batch.ids {
+                    batch.responses.push(timed_out(&id));
+                    abandoned.entry(key.clone()).or_default().push((id, Instant::now()));
+                }
+                expired.push((key.clone(), serde_json::Value::Array(batch.responses)));
+            }
+        }
+        pending.retain(|_, waiting| !waiting.is_empty());
+        expired
+    }
+
+    pub async fn forget(&self, key: &str) {
+        let mut batches = self.batches.lock().await;
+        batches.pending.remove(key);
+        batches.abandoned.remove(key);
+    }
+}
+
+fn timed_out(id: &serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": -32001, "message": "Timed out waiting for response" },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn response(id: i64) -> serde_json::Value {
+        json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+    }
+
+    #[tokio::test]
+    async fn releases_batch_once_every_id_is_answered() {
+        let collector = BatchCollector::default();
+        collector.register("s", vec![json!(1), json!(2)]).await;
+        assert!(matches!(collector.collect("s", response(2)).await, Collected::Held));
+        let Collected::Complete(batch) = collector.collect("s", response(1)).await else {
+            panic!("batch was not completed");
+        };
+        assert_eq!(batch, json!([response(2), response(1)]));
+        assert!(matches!(collector.collect("s", response(3)).await, Collected::Pass(_)));
+    }
+
+    #[tokio::test]
+    async fn expired_batch_reports_missing_ids_and_drops_late_responses() {
+        let collector = BatchCollector::default();
+        collector.register("s", vec![json!(1), json!(2)]).await;
+        assert!(matches!(collector.collect("s", response(1)).await, Collected::Held));
+        assert!(collector.expire(Duration::from_secs(60)).await.is_empty());
+
+        let expired = collector.expire(Duration::ZERO).await;
+        assert_eq!(expired.len(), 1);
+        let (key, batch) = &expired[0];
+        assert_eq!(key, "s");
+        assert_eq!(batch[0], response(1));
+        assert_eq!(batch[1]["id"], 2);
+        assert_eq!(batch[1]["error"]["code"], -32001);
+
+        assert!(matches!(collector.collect("s", response(2)).await, Collected::Late));
+        assert!(matches!(collector.collect("s", response(2)).await, Collected::Pass(_)));
+    }
+}
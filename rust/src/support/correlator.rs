@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+
+use crate::support::jsonrpc::is_response;
+
+// JSON-RPC ids may be strings or numbers and `1` and `"1"` are distinct ids,
+// so the key keeps the kind alongside the textual value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RequestId {
+    String(String),
+    Number(String),
+}
+
+impl RequestId {
+    pub fn from_value(id: &serde_json::Value) -> Option<Self> {
+        match id {
+            serde_json::Value::String(id) => Some(RequestId::String(id.clone())),
+            serde_json::Value::Number(id) => Some(RequestId::Number(id.to_string())),
+            _ => None,
+        }
+    }
+
+    pub fn to_value(&self) -> serde_json::Value {
+        match self {
+            RequestId::String(id) => serde_json::Value::String(id.clone()),
+            RequestId::Number(id) => serde_json::from_str(id).unwrap_or(serde_json::Value::Null),
+        }
+    }
+}
+
+struct Entry<T> {
+    value: T,
+    since: Instant,
+}
+
+pub struct Correlator<T> {
+    pending: Mutex<HashMap<RequestId, Entry<T>>>,
+}
+
+impl<T> Default for Correlator<T> {
+    fn default() -> Self {
+        Self {
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T> Correlator<T> {
+    pub async fn register(&self, id: &serde_json::Value, value: T) -> Result<RequestId, String> {
+        let key = RequestId::from_value(id).ok_or_else(|| format!("Invalid request id: {id}"))?;
+        let mut pending = self.pending.lock().await;
+        if pending.contains_key(&key) {
+            return Err(format!("Request id {id} is already in flight"));
+        }
+        pending.insert(
+            key.clone(),
+            Entry {
+                value,
+                since: Instant::now(),
+            },
+        );
+        Ok(key)
+    }
+
+    pub async fn resolve(&self, message: &serde_json::Value) -> Option<(RequestId, T)> {
+        if !is_response(message) {
+            return None;
+        }
+        let key = RequestId::from_value(message.get("id")?)?;
+        let entry = self.pending.lock().await.remove(&key)?;
+        Some((key, entry.value))
+    }
+
+    pub async fn cancel(&self, key: &RequestId) -> Option<T> {
+        self.pending.lock().await.remove(key).map(|entry| entry.value)
+    }
+
+    pub async fn retain(&self, mut keep: impl FnMut(&T) -> bool) {
+        self.pending.lock().await.retain(|_, entry| keep(&entry.value));
+    }
+
+    pub async fn expire(&self, max_age: Duration) -> Vec<(RequestId, T)> {
+        let mut pending = self.pending.lock().await;
+        let expired: Vec<RequestId> = pending
+            .iter()
+            .filter(|(_, entry)| entry.since.elapsed() >= max_age)
+            .map(|(key, _)| key.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|key| pending.remove(&key).map(|entry| (key, entry.value)))
+            .collect()
+    }
+
+    pub async fn drain(&self) -> Vec<(RequestId, T)> {
+        self.pending
+            .lock()
+            .await
+            .drain()
+            .map(|(key, entry)| (key, entry.value))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn response(id: serde_json::Value) -> serde_json::Value {
+        json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+    }
+
+    #[tokio::test]
+    async fn numeric_and_string_ids_are_distinct() {
+        let correlator = Correlator::default();
+        let number = correlator.register(&json!(1), "number").await.unwrap();
+        let string = correlator.register(&json!("1"), "string").await.unwrap();
+        assert_ne!(number, string);
+
+        assert_eq!(correlator.resolve(&response(json!("1"))).await, Some((string, "string")));
+        assert_eq!(correlator.resolve(&response(json!("1"))).await, None);
+        assert_eq!(correlator.resolve(&response(json!(1))).await, Some((number, "number")));
+    }
+
+    #[tokio::test]
+    async fn rejects_duplicate_and_invalid_ids() {
+        let correlator = Correlator::default();
+        correlator.register(&json!(7), ()).await.unwrap();
+        assert!(correlator.register(&json!(7), ()).await.is_err());
+        assert!(correlator.register(&json!(null), ()).await.is_err());
+    }
+
+    #[test]
+    fn to_value_keeps_the_id_kind() {
+        assert_eq!(RequestId::from_value(&json!(42)).unwrap().to_value(), json!(42));
+        assert_eq!(RequestId::from_value(&json!(-1.5)).unwrap().to_value(), json!(-1.5));
+        assert_eq!(RequestId::from_value(&json!("42")).unwrap().to_value(), json!("42"));
+    }
+
+    #[tokio::test]
+    async fn expire_returns_only_timed_out_entries() {
+        let correlator = Correlator::default();
+        let old = correlator.register(&json!(1), "old").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        correlator.register(&json!(2), "new").await.unwrap();
+
+        assert_eq!(correlator.expire(Duration::from_millis(40)).await, vec![(old, "old")]);
+        assert!(correlator.expire(Duration::from_millis(40)).await.is_empty());
+        assert!(correlator.resolve(&response(json!(2))).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn resolve_ignores_requests_and_notifications() {
+        let correlator = Correlator::default();
+        correlator.register(&json!(1), ()).await.unwrap();
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "ping" });
+        let notification = json!({ "jsonrpc": "2.0", "method": "notifications/progress" });
+        assert_eq!(correlator.resolve(&request).await, None);
+        assert_eq!(correlator.resolve(&notification).await, None);
+        assert!(correlator.resolve(&response(json!(1))).await.is_some());
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::VecDeque;
+
+pub struct EventStore {
+    capacity: usize,
+    next_id: u64,
+    events: VecDeque<(u64, serde_json::Value)>,
+}
+
+pub struct Replay {
+    pub events: Vec<(u64, serde_json::Value)>,
+    pub missed: u64,
+}
+
+impl EventStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            next_id: 1,
+            events: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, message: serde_json::Value) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back((id, message));
+        id
+    }
+
+    pub fn last_id(&self) -> u64 {
+        self.next_id - 1
+    }
+
+    // Returns everything stored after `last_id`, plus how many events in that
+    // range were already evicted and can no longer be replayed.
+    pub fn since(&self, last_id: u64) -> Replay {
+        let last_id = last_id.min(self.last_id());
+        let oldest = self
+            .events
+            .front()
+            .map(|(id, _)| *id)
+            .unwrap_or(self.next_id);
+        let missed = oldest.saturating_sub(last_id + 1);
+        let events = self
+            .events
+            .iter()
+            .filter(|(id, _)| *id > last_id)
+            .cloned()
+            .collect();
+        Replay { events, missed }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn store(capacity: usize, count: u64) -> EventStore {
+        let mut store = EventStore::new(capacity);
+        for n in 1..=count {
+            store.push(json!(n));
+        }
+        store
+    }
+
+    fn ids(replay: &Replay) -> Vec<u64> {
+        replay.events.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn replays_events_after_last_id() {
+        let store = store(8, 5);
+        let replay = store.since(3);
+        assert_eq!(ids(&replay), vec![4, 5]);
+        assert_eq!(replay.events[0].1, json!(4));
+        assert_eq!(replay.missed, 0);
+        assert!(store.since(5).events.is_empty());
+    }
+
+    #[test]
+    fn unknown_id_replays_nothing() {
+        let store = store(8, 5);
+        let replay = store.since(99);
+        assert!(replay.events.is_empty());
+        assert_eq!(replay.missed, 0);
+    }
+
+    #[test]
+    fn counts_events_evicted_past_capacity() {
+        let store = store(3, 10);
+        let replay = store.since(2);
+        assert_eq!(ids(&replay), vec![8, 9, 10]);
+        assert_eq!(replay.missed, 5);
+        assert_eq!(store.since(0).missed, 7);
+        assert_eq!(store.since(7).missed, 0);
+    }
+}
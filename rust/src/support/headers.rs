@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use axum::http::header::HeaderName;
+use axum::http::{HeaderMap, HeaderValue};
+use axum::response::Response;
+
+use crate::runtime::store::RuntimeArgsStore;
+
+pub fn header_map_from(headers: &HashMap<String, String>) -> HeaderMap {
+    let mut map = HeaderMap::new();
+    for (k, v) in headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(k.as_bytes()),
+            HeaderValue::from_str(v),
+        ) {
+            map.insert(name, value);
+        }
+    }
+    map
+}
+
+pub fn merge_headers(base: &HeaderMap, overlay: &HashMap<String, String>) -> HeaderMap {
+    let mut merged = base.clone();
+    for (k, v) in overlay {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(k.as_bytes()),
+            HeaderValue::from_str(v),
+        ) {
+            merged.insert(name, value);
+        }
+    }
+    merged
+}
+
+// The configured headers, overlaid with the runtime ones, that a server output
+// adds to its responses. With a remote source the configured headers are
+// credentials for the upstream server, so they are never echoed to clients.
+#[derive(Clone)]
+pub struct ResponseHeaders {
+    base: HeaderMap,
+    runtime: RuntimeArgsStore,
+    remote: bool,
+}
+
+impl ResponseHeaders {
+    pub fn new(headers: &HashMap<String, String>, runtime: RuntimeArgsStore, remote: bool) -> Self {
+        Self {
+            base: header_map_from(headers),
+            runtime,
+            remote,
+        }
+    }
+
+    pub async fn apply(&self, session_id: Option<&str>, response: &mut Response) {
+        if self.remote {
+            return;
+        }
+        let runtime = self.runtime.get_effective(session_id).await;
+        let headers = merge_headers(&self.base, &runtime.headers);
+        let header_map = response.headers_mut();
+        for (key, value) in headers.iter() {
+            header_map.insert(key, value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::response::IntoResponse;
+
+    use super::*;
+    use crate::types::RuntimeArgs;
+
+    fn store() -> RuntimeArgsStore {
+        RuntimeArgsStore::new(RuntimeArgs {
+            headers: HashMap::from([("x-runtime".to_string(), "runtime".to_string())]),
+            ..RuntimeArgs::default()
+        })
+    }
+
+    fn configured() -> HashMap<String, String> {
+        HashMap::from([
+            ("x-runtime".to_string(), "configured".to_string()),
+            ("authorization".to_string(), "Bearer upstream".to_string()),
+        ])
+    }
+
+    #[tokio::test]
+    async fn local_source_headers_overlay_runtime_on_configured() {
+        let mut response = ().into_response();
+        ResponseHeaders::new(&configured(), store(), false)
+            .apply(None, &mut response)
+            .await;
+        assert_eq!(response.headers()["x-runtime"], "runtime");
+        assert_eq!(response.headers()["authorization"], "Bearer upstream");
+    }
+
+    #[tokio::test]
+    async fn remote_source_headers_are_not_echoed() {
+        let mut response = ().into_response();
+        ResponseHeaders::new(&configured(), store(), true)
+            .apply(None, &mut response)
+            .await;
+        assert!(response.headers().get("authorization").is_none());
+        assert!(response.headers().get("x-runtime").is_none());
+    }
+}
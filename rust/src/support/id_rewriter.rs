@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tokio::sync::Mutex;
+
+struct Route {
+    session_id: String,
+    original_id: serde_json::Value,
+    progress_token: Option<serde_json::Value>,
+}
+
+// Maps the ids of requests from many sessions into one id space for a shared
+// child. A request's progress token is replaced by its gateway id as well, so
+// tokens from different sessions cannot collide.
+#[derive(Default)]
+pub struct IdRewriter {
+    next: AtomicU64,
+    routes: Mutex<HashMap<u64, Route>>,
+}
+
+impl IdRewriter {
+    // Returns false when the message must not reach the child: a cancellation
+    // for a request the session does not have in flight.
+    pub async fn rewrite_client_message(
+        &self,
+        session_id: &str,
+        message: &mut serde_json::Value,
+    ) -> bool {
+        if message.get("method").and_then(|m| m.as_str()) == Some("notifications/cancelled") {
+            return self.rewrite_cancel(session_id, message).await;
+        }
+        if message.get("method").is_none() {
+            return true;
+        }
+        let Some(original_id) = message.get("id").cloned() else {
+            return true;
+        };
+        let gateway_id = self.next.fetch_add(1, Ordering::SeqCst);
+        let progress_token = message
+            .pointer_mut("/params/_meta/progressToken")
+            .map(|token| std::mem::replace(token, serde_json::Value::from(gateway_id)));
+        self.routes.lock().await.insert(
+            gateway_id,
+            Route {
+                session_id: session_id.to_string(),
+                original_id,
+                progress_token,
+            },
+        );
+        message["id"] = serde_json::Value::from(gateway_id);
+        true
+    }
+
+    async fn rewrite_cancel(&self, session_id: &str, message: &mut serde_json::Value) -> bool {
+        let Some(request_id) = message.pointer_mut("/params/requestId") else {
+            return false;
+        };
+        let routes = self.routes.lock().await;
+        let gateway_id = routes.iter().find_map(|(gateway_id, route)| {
+            (route.session_id == session_id && route.original_id == *request_id)
+                .then_some(*gateway_id)
+        });
+        match gateway_id {
+            Some(gateway_id) => {
+                *request_id = serde_json::Value::from(gateway_id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub async fn restore_response(&self, message: &mut serde_json::Value) -> Option<String> {
+        if message.get("method").is_some() {
+            return None;
+        }
+        let gateway_id = message.get("id")?.as_u64()?;
+        let route = {
+            let mut routes = self.routes.lock().await;
+            routes.remove(&gateway_id)?
+        };
+        message
+            .as_object_mut()?
+            .insert("id".to_string(), route.original_id);
+        Some(route.session_id)
+    }
+
+    // Restores the client's token on a progress notification and returns the
+    // session that owns it.
+    pub async fn restore_progress(&self, message: &mut serde_json::Value) -> Option<String> {
+        let token = message.pointer_mut("/params/progressToken")?;
+        let gateway_id = token.as_u64()?;
+        let routes = self.routes.lock().await;
+        let route = routes.get(&gateway_id)?;
+        *token = route.progress_token.clone()?;
+        Some(route.session_id.clone())
+    }
+
+    pub async fn forget_session(&self, session_id: &str) {
+        let mut routes = self.routes.lock().await;
+        routes.retain(|_, route| route.session_id != session_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn routes_responses_to_their_session() {
+        let ids = IdRewriter::default();
+        let mut a = json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/call" });
+        let mut b = json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/call" });
+        assert!(ids.rewrite_client_message("a", &mut a).await);
+        assert!(ids.rewrite_client_message("b", &mut b).await);
+        assert_ne!(a["id"], b["id"]);
+
+        let mut response = json!({ "jsonrpc": "2.0", "id": b["id"], "result": {} });
+        assert_eq!(ids.restore_response(&mut response).await.as_deref(), Some("b"));
+        assert_eq!(response["id"], 1);
+        assert_eq!(ids.restore_response(&mut response).await, None);
+    }
+
+    #[tokio::test]
+    async fn cancels_only_own_requests() {
+        let ids = IdRewriter::default();
+        let mut request = json!({ "jsonrpc": "2.0", "id": 42, "method": "tools/call" });
+        ids.rewrite_client_message("a", &mut request).await;
+        let gateway_id = request["id"].clone();
+
+        let cancel = |request_id: serde_json::Value| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": "notifications/cancelled",
+                "params": { "requestId": request_id },
+            })
+        };
+        let mut foreign = cancel(gateway_id.clone());
+        assert!(!ids.rewrite_client_message("b", &mut foreign).await);
+        let mut unknown = cancel(json!(7));
+        assert!(!ids.rewrite_client_message("a", &mut unknown).await);
+        let mut own = cancel(json!(42));
+        assert!(ids.rewrite_client_message("a", &mut own).await);
+        assert_eq!(own["params"]["requestId"], gateway_id);
+    }
+
+    #[tokio::test]
+    async fn routes_progress_to_the_token_owner() {
+        let ids = IdRewriter::default();
+        let mut a = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": { "_meta": { "progressToken": "t" } },
+        });
+        let mut b = a.clone();
+        ids.rewrite_client_message("a", &mut a).await;
+        ids.rewrite_client_message("b", &mut b).await;
+        let token = b["params"]["_meta"]["progressToken"].clone();
+        assert_ne!(a["params"]["_meta"]["progressToken"], token);
+
+        let mut progress = json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/progress",
+            "params": { "progressToken": token, "progress": 1 },
+        });
+        assert_eq!(ids.restore_progress(&mut progress).await.as_deref(), Some("b"));
+        assert_eq!(progress["params"]["progressToken"], "t");
+
+        let mut stray = json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/progress",
+            "params": { "progressToken": "t", "progress": 1 },
+        });
+        assert_eq!(ids.restore_progress(&mut stray).await, None);
+    }
+}
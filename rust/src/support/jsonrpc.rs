@@ -0,0 +1,181 @@
+use uuid::Uuid;
+
+pub fn is_request(message: &serde_json::Value) -> bool {
+    message.get("method").is_some() && message.get("id").is_some()
+}
+
+pub fn is_response(message: &serde_json::Value) -> bool {
+    message.get("method").is_none()
+        && message.get("id").is_some()
+        && (message.get("result").is_some() || message.get("error").is_some())
+}
+
+pub fn is_initialize_request(message: &serde_json::Value) -> bool {
+    message
+        .get("method")
+        .and_then(|method| method.as_str())
+        .map(|method| method == "initialize")
+        .unwrap_or(false)
+}
+
+pub fn declares_sampling(initialize: &serde_json::Value) -> bool {
+    initialize
+        .get("params")
+        .and_then(|params| params.get("capabilities"))
+        .and_then(|capabilities| capabilities.get("sampling"))
+        .is_some()
+}
+
+pub fn auto_init_id() -> String {
+    format!(
+        "init_{}_{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis(),
+        Uuid::new_v4()
+    )
+}
+
+pub fn client_capabilities() -> serde_json::Value {
+    serde_json::json!({
+        "roots": { "listChanged": true },
+        "sampling": {}
+    })
+}
+
+pub fn create_initialize_request(
+    id: &str,
+    protocol_version: &str,
+    capabilities: serde_json::Value,
+) -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": protocol_version,
+            "capabilities": capabilities,
+            "clientInfo": {
+                "name": "supergateway",
+                "version": crate::support::version::get_version()
+            }
+        }
+    })
+}
+
+pub fn create_initialized_notification() -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/initialized"
+    })
+}
+
+pub fn create_cancelled_notification(id: &serde_json::Value, reason: &str) -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/cancelled",
+        "params": {
+            "requestId": id,
+            "reason": reason
+        }
+    })
+}
+
+pub async fn parse_response_payload(resp: reqwest::Response) -> Result<serde_json::Value, String> {
+    let status = resp.status();
+    let text = resp.text().await.map_err(|err| err.to_string())?;
+    if text.trim().is_empty() {
+        if status.is_success() {
+            return Err("Empty response".to_string());
+        }
+        return Err(format!("Request failed with status {}", status));
+    }
+    let json: serde_json::Value =
+        serde_json::from_str(&text).map_err(|err| err.to_string())?;
+    if !status.is_success() {
+        if let Some(error) = json.get("error") {
+            return Ok(serde_json::json!({ "error": error }));
+        }
+        return Err(format!("Request failed with status {}", status));
+    }
+    if json.get("error").is_some() {
+        return Ok(serde_json::json!({ "error": json.get("error").cloned().unwrap_or_default() }));
+    }
+    if let Some(result) = json.get("result") {
+        return Ok(serde_json::json!({ "result": result }));
+    }
+    Ok(serde_json::json!({ "result": json }))
+}
+
+pub fn wrap_response(req: &serde_json::Value, payload: serde_json::Value) -> serde_json::Value {
+    let jsonrpc = req
+        .get("jsonrpc")
+        .cloned()
+        .unwrap_or_else(|| serde_json::Value::String("2.0".to_string()));
+    let id = req
+        .get("id")
+        .cloned()
+        .unwrap_or(serde_json::Value::Null);
+
+    let mut response = serde_json::Map::new();
+    response.insert("jsonrpc".to_string(), jsonrpc);
+    response.insert("id".to_string(), id);
+
+    if let Some(error) = payload.get("error") {
+        if let Some(code) = error.get("code").and_then(|v| v.as_i64()) {
+            let message = error
+                .get("message")
+                .and_then(|v| v.as_str())
+                .unwrap_or("Internal error");
+            response.insert(
+                "error".to_string(),
+                serde_json::json!({
+                    "code": code,
+                    "message": normalize_error_message(code, message),
+                }),
+            );
+        } else {
+            response.insert("error".to_string(), error.clone());
+        }
+    } else if let Some(result) = payload.get("result") {
+        response.insert("result".to_string(), result.clone());
+    }
+
+    serde_json::Value::Object(response)
+}
+
+pub fn error_payload(code: i64, message: impl Into<String>) -> serde_json::Value {
+    serde_json::json!({
+        "error": {
+            "code": code,
+            "message": message.into(),
+        }
+    })
+}
+
+fn normalize_error_message(code: i64, message: &str) -> String {
+    let prefix = format!("MCP error {code}:");
+    if message.starts_with(&prefix) {
+        message[prefix.len()..].trim().to_string()
+    } else {
+        message.to_string()
+    }
+}
+
+pub fn request_ids(messages: &[serde_json::Value]) -> Vec<serde_json::Value> {
+    messages
+        .iter()
+        .filter(|message| is_request(message))
+        .filter_map(|message| message.get("id").cloned())
+        .collect()
+}
+
+// An empty batch has no request to answer, so its error carries a null id.
+pub fn empty_batch_error() -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "error": { "code": -32600, "message": "Invalid Request: empty batch" },
+        "id": serde_json::Value::Null
+    })
+}
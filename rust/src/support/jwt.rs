@@ -0,0 +1,300 @@
+use std::time::{Duration, Instant};
+
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use tokio::sync::RwLock;
+
+use crate::support::auth::Principal;
+
+// A URL-backed key set is refetched for an unknown `kid` at most this often.
+const JWKS_REFETCH_INTERVAL: Duration = Duration::from_secs(30);
+
+pub enum JwksSource {
+    File(String),
+    Url(String),
+}
+
+pub enum JwtError {
+    Invalid(String),
+    InsufficientScope,
+}
+
+struct CachedKeys {
+    keys: JwkSet,
+    fetched_at: Option<Instant>,
+}
+
+// Validates OAuth 2.1 access tokens issued for this gateway.
+pub struct JwtValidator {
+    source: JwksSource,
+    cache: RwLock<CachedKeys>,
+    http: reqwest::Client,
+    pub issuer: String,
+    pub audience: String,
+    pub scopes: Vec<String>,
+}
+
+impl JwtValidator {
+    pub fn new(
+        source: JwksSource,
+        issuer: String,
+        audience: String,
+        scopes: Vec<String>,
+    ) -> Result<Self, String> {
+        let keys = match &source {
+            JwksSource::File(path) => {
+                let contents = std::fs::read_to_string(path)
+                    .map_err(|err| format!("Failed to read JWKS file {path}: {err}"))?;
+                serde_json::from_str(&contents)
+                    .map_err(|err| format!("Invalid JWKS file {path}: {err}"))?
+            }
+            JwksSource::Url(_) => JwkSet { keys: Vec::new() },
+        };
+        Ok(Self {
+            source,
+            cache: RwLock::new(CachedKeys {
+                keys,
+                fetched_at: None,
+            }),
+            http: reqwest::Client::new(),
+            issuer,
+            audience,
+            scopes,
+        })
+    }
+
+    pub async fn validate(&self, token: &str) -> Result<Principal, JwtError> {
+        let header = decode_header(token).map_err(|err| JwtError::Invalid(err.to_string()))?;
+        if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+            return Err(JwtError::Invalid(format!("Unsupported algorithm {:?}", header.alg)));
+        }
+        let key = self.decoding_key(header.kid.as_deref(), header.alg).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.validate_nbf = true;
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+        let claims = decode::<serde_json::Value>(token, &key, &validation)
+            .map_err(|err| JwtError::Invalid(err.to_string()))?
+            .claims;
+
+        let granted = granted_scopes(&claims);
+        if !self.scopes.iter().all(|scope| granted.contains(scope)) {
+            return Err(JwtError::InsufficientScope);
+        }
+        let name = claims
+            .get("sub")
+            .or_else(|| claims.get("client_id"))
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| JwtError::Invalid("Token has no subject".to_string()))?;
+        Ok(Principal {
+            name: name.to_string(),
+        })
+    }
+
+    async fn decoding_key(
+        &self,
+        kid: Option<&str>,
+        alg: Algorithm,
+    ) -> Result<DecodingKey, JwtError> {
+        if let Some(key) = self.find_key(kid, alg).await? {
+            return Ok(key);
+        }
+        if let JwksSource::Url(url) = &self.source {
+            let mut cache = self.cache.write().await;
+            let stale = cache
+                .fetched_at
+                .is_none_or(|at| at.elapsed() >= JWKS_REFETCH_INTERVAL);
+            if stale {
+                match self.fetch(url).await {
+                    Ok(keys) => cache.keys = keys,
+                    Err(err) => tracing::error!("Failed to fetch JWKS from {url}: {err}"),
+                }
+                cache.fetched_at = Some(Instant::now());
+            }
+        }
+        self.find_key(kid, alg)
+            .await?
+            .ok_or_else(|| JwtError::Invalid("No matching signing key".to_string()))
+    }
+
+    async fn find_key(
+        &self,
+        kid: Option<&str>,
+        alg: Algorithm,
+    ) -> Result<Option<DecodingKey>, JwtError> {
+        let cache = self.cache.read().await;
+        let jwk = match kid {
+            Some(kid) => cache.keys.find(kid),
+            None if cache.keys.keys.len() == 1 => cache.keys.keys.first(),
+            None => None,
+        };
+        let Some(jwk) = jwk else {
+            return Ok(None);
+        };
+        if let Some(key_alg) = &jwk.common.key_algorithm {
+            if key_alg.to_string().parse::<Algorithm>().ok() != Some(alg) {
+                return Err(JwtError::Invalid(format!(
+                    "Token algorithm {alg:?} does not match key algorithm {key_alg}"
+                )));
+            }
+        }
+        DecodingKey::from_jwk(jwk)
+            .map(Some)
+            .map_err(|err| JwtError::Invalid(err.to_string()))
+    }
+
+    async fn fetch(&self, url: &str) -> Result<JwkSet, String> {
+        let resp = self.http.get(url).send().await.map_err(|err| err.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("status {}", resp.status()));
+        }
+        resp.json().await.map_err(|err| err.to_string())
+    }
+}
+
+// Scopes come as a space-separated `scope` string or an `scp` list.
+fn granted_scopes(claims: &serde_json::Value) -> Vec<String> {
+    match claims.get("scope").or_else(|| claims.get("scp")) {
+        Some(serde_json::Value::String(scopes)) => {
+            scopes.split_whitespace().map(str::to_string).collect()
+        }
+        Some(serde_json::Value::Array(scopes)) => scopes
+            .iter()
+            .filter_map(|scope| scope.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{encode, get_current_timestamp, EncodingKey, Header};
+    use serde_json::json;
+
+    use super::*;
+
+    const ISSUER: &str = "https://issuer.test";
+    const AUDIENCE: &str = "https://gateway.test";
+    const RSA_KEY: &str = include_str!("../../tests/fixtures/jwt/rsa.pem");
+    const OTHER_KEY: &str = include_str!("../../tests/fixtures/jwt/other.pem");
+
+    fn validator(scopes: &[&str]) -> JwtValidator {
+        let jwks = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/jwt/jwks.json");
+        JwtValidator::new(
+            JwksSource::File(jwks.to_string()),
+            ISSUER.to_string(),
+            AUDIENCE.to_string(),
+            scopes.iter().map(|scope| scope.to_string()).collect(),
+        )
+        .unwrap()
+    }
+
+    fn claims() -> serde_json::Value {
+        let now = get_current_timestamp();
+        json!({
+            "iss": ISSUER,
+            "aud": AUDIENCE,
+            "sub": "alice",
+            "iat": now,
+            "exp": now + 600,
+            "scope": "mcp:read mcp:write",
+        })
+    }
+
+    fn sign_with(pem: &str, alg: Algorithm, kid: &str, claims: &serde_json::Value) -> String {
+        let mut header = Header::new(alg);
+        header.kid = Some(kid.to_string());
+        let key = EncodingKey::from_rsa_pem(pem.as_bytes()).unwrap();
+        encode(&header, claims, &key).unwrap()
+    }
+
+    fn sign(claims: &serde_json::Value) -> String {
+        sign_with(RSA_KEY, Algorithm::RS256, "test-rsa", claims)
+    }
+
+    async fn rejected(validator: &JwtValidator, token: &str) -> String {
+        match validator.validate(token).await {
+            Ok(principal) => panic!("token accepted for {}", principal.name),
+            Err(JwtError::Invalid(err)) => err,
+            Err(JwtError::InsufficientScope) => "insufficient scope".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn accepts_valid_token() {
+        let token = sign(&claims());
+        match validator(&["mcp:read"]).validate(&token).await {
+            Ok(principal) => assert_eq!(principal.name, "alice"),
+            Err(_) => panic!("valid token rejected"),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_issuer_and_audience() {
+        let validator = validator(&[]);
+        let mut wrong_iss = claims();
+        wrong_iss["iss"] = json!("https://elsewhere.test");
+        assert!(rejected(&validator, &sign(&wrong_iss)).await.contains("Issuer"));
+        let mut wrong_aud = claims();
+        wrong_aud["aud"] = json!("https://elsewhere.test");
+        assert!(rejected(&validator, &sign(&wrong_aud)).await.contains("Audience"));
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_token() {
+        let mut expired = claims();
+        expired["exp"] = json!(get_current_timestamp() - 600);
+        assert!(rejected(&validator(&[]), &sign(&expired)).await.contains("Expired"));
+    }
+
+    #[tokio::test]
+    async fn rejects_token_not_yet_valid() {
+        let mut future = claims();
+        future["nbf"] = json!(get_current_timestamp() + 300);
+        assert!(rejected(&validator(&[]), &sign(&future)).await.contains("Immature"));
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_scope() {
+        let token = sign(&claims());
+        let validator = validator(&["mcp:read", "mcp:admin"]);
+        assert!(matches!(
+            validator.validate(&token).await,
+            Err(JwtError::InsufficientScope)
+        ));
+    }
+
+    #[tokio::test]
+    async fn accepts_scp_list() {
+        let mut scp = claims();
+        scp.as_object_mut().unwrap().remove("scope");
+        scp["scp"] = json!(["mcp:read", "mcp:admin"]);
+        assert!(validator(&["mcp:admin"]).validate(&sign(&scp)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_symmetric_algorithms() {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("test-rsa".to_string());
+        let key = EncodingKey::from_secret(b"shared secret");
+        let token = encode(&header, &claims(), &key).unwrap();
+        assert!(rejected(&validator(&[]), &token).await.contains("Unsupported algorithm"));
+    }
+
+    #[tokio::test]
+    async fn rejects_algorithm_not_matching_key() {
+        let token = sign_with(RSA_KEY, Algorithm::RS384, "test-rsa", &claims());
+        assert!(rejected(&validator(&[]), &token).await.contains("does not match"));
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_kid_and_foreign_signature() {
+        let validator = validator(&[]);
+        let unknown = sign_with(RSA_KEY, Algorithm::RS256, "rotated", &claims());
+        assert!(rejected(&validator, &unknown).await.contains("No matching signing key"));
+        let forged = sign_with(OTHER_KEY, Algorithm::RS256, "test-rsa", &claims());
+        assert!(rejected(&validator, &forged).await.contains("InvalidSignature"));
+    }
+}
@@ -4,3 +4,15 @@ pub mod stdio_child;
 pub mod telemetry;
 pub mod version;
 pub mod session_access_counter;
+pub mod id_rewriter;
+pub mod headers;
+pub mod jsonrpc;
+pub mod backoff;
+pub mod batch;
+pub mod event_store;
+pub mod correlator;
+pub mod responder;
+pub mod sampling;
+pub mod auth;
+pub mod jwt;
+pub mod oauth_client;
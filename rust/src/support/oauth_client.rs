@@ -0,0 +1,553 @@
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tokio::sync::{mpsc, oneshot, Mutex, Notify};
+use tokio::time::Instant;
+
+use crate::config::Config;
+use crate::runtime::store::{RuntimeArgsStore, RuntimeArgsUpdate};
+use crate::runtime::{RuntimeScope, RuntimeUpdate, RuntimeUpdateRequest};
+use crate::support::backoff::Backoff;
+use crate::types::HeadersMap;
+
+const AUTHORIZATION: &str = "Authorization";
+// Tokens are renewed a fifth of their lifetime early, but no more than this.
+const MAX_REFRESH_LEAD: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+#[derive(Default)]
+struct TokenState {
+    access_token: Option<String>,
+    refresh_token: Option<String>,
+    refresh_at: Option<Instant>,
+}
+
+struct Publisher {
+    runtime: RuntimeArgsStore,
+    updates: mpsc::Sender<RuntimeUpdateRequest>,
+}
+
+// Obtains access tokens for the upstream server with the client-credentials
+// or refresh-token grant and keeps the `Authorization` runtime header fresh.
+pub struct OAuthClient {
+    http: reqwest::Client,
+    token_url: String,
+    client_id: String,
+    client_secret: Option<String>,
+    scope: Option<String>,
+    state: Mutex<TokenState>,
+    renewed: Notify,
+    publisher: OnceLock<Publisher>,
+}
+
+impl std::fmt::Debug for OAuthClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "OAuthClient({} at {})", self.client_id, self.token_url)
+    }
+}
+
+impl OAuthClient {
+    pub fn from_config(config: &Config) -> Option<Arc<Self>> {
+        Some(Self::new(
+            config.oauth2_token_url.clone()?,
+            config.oauth2_client_id.clone().unwrap_or_default(),
+            config.oauth2_client_secret.clone(),
+            config.oauth2_refresh_token.clone(),
+            config.oauth2_scope.clone(),
+        ))
+    }
+
+    fn new(
+        token_url: String,
+        client_id: String,
+        client_secret: Option<String>,
+        refresh_token: Option<String>,
+        scope: Option<String>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            http: reqwest::Client::new(),
+            token_url,
+            client_id,
+            client_secret,
+            scope,
+            state: Mutex::new(TokenState {
+                refresh_token,
+                ..Default::default()
+            }),
+            renewed: Notify::new(),
+            publisher: OnceLock::new(),
+        })
+    }
+
+    // Fetches the first token straight into the store, before any upstream
+    // connection exists, then keeps it renewed through runtime updates.
+    pub async fn start(
+        self: &Arc<Self>,
+        runtime: RuntimeArgsStore,
+        updates: mpsc::Sender<RuntimeUpdateRequest>,
+    ) -> Result<(), String> {
+        let token = {
+            let mut state = self.state.lock().await;
+            self.fetch(&mut state).await?
+        };
+        runtime.update_global(with_token(&runtime, &token).await).await;
+        let _ = self.publisher.set(Publisher { runtime, updates });
+        tokio::spawn(self.clone().refresh_loop());
+        Ok(())
+    }
+
+    // Called after the upstream answered 401: returns `headers` with a fresh
+    // token, fetching one unless another caller already replaced the token
+    // that was rejected.
+    pub async fn reauthorize(&self, headers: &HeadersMap) -> Result<HeadersMap, String> {
+        let rejected = headers
+            .get(AUTHORIZATION)
+            .and_then(|v| v.strip_prefix("Bearer "));
+        let token = self.refresh(rejected).await?;
+        let mut headers = headers.clone();
+        headers.insert(AUTHORIZATION.to_string(), format!("Bearer {token}"));
+        Ok(headers)
+    }
+
+    async fn refresh(&self, rejected: Option<&str>) -> Result<String, String> {
+        let mut state = self.state.lock().await;
+        if let Some(current) = state.access_token.clone() {
+            if rejected != Some(current.as_str()) {
+                drop(state);
+                // The header was dropped by a runtime update; put it back.
+                if rejected.is_none() {
+                    self.publish(&current).await;
+                }
+                return Ok(current);
+            }
+        }
+        let token = self.fetch(&mut state).await?;
+        drop(state);
+        self.publish(&token).await;
+        Ok(token)
+    }
+
+    async fn refresh_loop(self: Arc<Self>) {
+        let mut backoff = Backoff::default();
+        loop {
+            let refresh_at = self.state.lock().await.refresh_at;
+            match refresh_at {
+                Some(at) => tokio::select! {
+                    _ = tokio::time::sleep_until(at) => {}
+                    _ = self.renewed.notified() => continue,
+                },
+                // Without an expiry the token is only replaced after a 401.
+                None => {
+                    self.renewed.notified().await;
+                    continue;
+                }
+            }
+            let current = self.state.lock().await.access_token.clone();
+            match self.refresh(current.as_deref()).await {
+                Ok(_) => backoff.reset(),
+                Err(err) => {
+                    tracing::error!("Failed to refresh OAuth access token: {err}");
+                    tokio::time::sleep(backoff.next_delay()).await;
+                }
+            }
+        }
+    }
+
+    async fn fetch(&self, state: &mut TokenState) -> Result<String, String> {
+        let response = match state.refresh_token.clone() {
+            Some(refresh_token) => {
+                match self
+                    .request(&[("grant_type", "refresh_token"), ("refresh_token", &refresh_token)])
+                    .await
+                {
+                    Ok(response) => response,
+                    Err(err) if self.client_secret.is_some() => {
+                        tracing::warn!(
+                            "Refresh token rejected ({err}), falling back to client credentials"
+                        );
+                        state.refresh_token = None;
+                        self.request(&[("grant_type", "client_credentials")]).await?
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            None => self.request(&[("grant_type", "client_credentials")]).await?,
+        };
+
+        match response.expires_in {
+            Some(secs) => {
+                let lifetime = Duration::from_secs(secs);
+                let lead = (lifetime / 5).min(MAX_REFRESH_LEAD);
+                // A lifetime too long to represent is never refreshed early.
+                state.refresh_at = Instant::now().checked_add(lifetime - lead);
+                tracing::info!("Obtained OAuth access token, expires in {secs}s");
+            }
+            None => {
+                state.refresh_at = None;
+                tracing::info!("Obtained OAuth access token");
+            }
+        }
+        if response.refresh_token.is_some() {
+            state.refresh_token = response.refresh_token;
+        }
+        state.access_token = Some(response.access_token.clone());
+        self.renewed.notify_one();
+        Ok(response.access_token)
+    }
+
+    async fn request(&self, grant: &[(&str, &str)]) -> Result<TokenResponse, String> {
+        let mut form: Vec<(&str, &str)> = grant.to_vec();
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope));
+        }
+        let mut req = self.http.post(&self.token_url);
+        match &self.client_secret {
+            Some(secret) => req = req.basic_auth(&self.client_id, Some(secret)),
+            None => form.push(("client_id", &self.client_id)),
+        }
+        let resp = req.form(&form).send().await.map_err(|err| err.to_string())?;
+        let status = resp.status();
+        let text = resp.text().await.map_err(|err| err.to_string())?;
+        if !status.is_success() {
+            return Err(format!("Token endpoint returned {status}: {text}"));
+        }
+        serde_json::from_str(&text).map_err(|err| format!("Invalid token response: {err}"))
+    }
+
+    // Goes through the runtime update channel so the upstream picks the new
+    // header up the same way it would an admin update.
+    async fn publish(&self, token: &str) {
+        let Some(publisher) = self.publisher.get() else {
+            return;
+        };
+        let (respond_to, response) = oneshot::channel();
+        let request = RuntimeUpdateRequest {
+            update: RuntimeUpdate {
+                scope: RuntimeScope::Global,
+                update: with_token(&publisher.runtime, token).await,
+            },
+            respond_to,
+        };
+        if publisher.updates.send(request).await.is_err() {
+            tracing::error!("Runtime update channel closed");
+            return;
+        }
+        if let Ok(result) = response.await {
+            tracing::debug!("OAuth token update: {}", result.message);
+        }
+    }
+}
+
+// Sends an upstream request built with `headers`. A 401 with an OAuth client
+// configured is retried once with a fresh token.
+pub async fn send_authorized<F, Fut>(
+    oauth: Option<&OAuthClient>,
+    headers: &HeadersMap,
+    send: F,
+) -> Result<reqwest::Response, String>
+where
+    F: Fn(HeadersMap) -> Fut,
+    Fut: Future<Output = Result<reqwest::Response, String>>,
+{
+    let response = send(headers.clone()).await?;
+    let Some(oauth) = oauth.filter(|_| response.status() == StatusCode::UNAUTHORIZED) else {
+        return Ok(response);
+    };
+    let headers = oauth.reauthorize(headers).await?;
+    send(headers).await
+}
+
+// Replaces only the `Authorization` header so other runtime headers survive.
+async fn with_token(runtime: &RuntimeArgsStore, token: &str) -> RuntimeArgsUpdate {
+    let mut headers = runtime.get_effective(None).await.headers;
+    headers.insert(AUTHORIZATION.to_string(), format!("Bearer {token}"));
+    RuntimeArgsUpdate {
+        extra_cli_args: None,
+        env: None,
+        headers: Some(headers),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::convert::Infallible;
+
+    use axum::extract::State;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::sse::{Event, Sse};
+    use axum::response::{IntoResponse, Response};
+    use axum::routing::{get, post};
+    use axum::{Form, Json, Router};
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::runtime::RuntimeApplyResult;
+    use crate::transport::remote::{detect_transport, RemoteTransport};
+    use crate::transport::sse_client::SseClient;
+    use crate::types::RuntimeArgs;
+
+    struct TokenRequest {
+        authorization: Option<String>,
+        form: HashMap<String, String>,
+    }
+
+    #[derive(Default)]
+    struct Stub {
+        requests: std::sync::Mutex<Vec<TokenRequest>>,
+        reject_refresh: bool,
+        expires_in: Option<u64>,
+    }
+
+    impl Stub {
+        fn grants(&self) -> Vec<String> {
+            self.requests.lock().unwrap().iter().map(|r| r.form["grant_type"].clone()).collect()
+        }
+
+        // Tokens are numbered, so `token-1` is the first one issued.
+        fn issued(&self, n: usize) -> String {
+            format!("Bearer token-{n}")
+        }
+    }
+
+    async fn token(
+        State(stub): State<Arc<Stub>>,
+        headers: HeaderMap,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Response {
+        let refresh = form["grant_type"] == "refresh_token";
+        let mut requests = stub.requests.lock().unwrap();
+        requests.push(TokenRequest {
+            authorization: headers
+                .get("authorization")
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+            form,
+        });
+        if refresh && stub.reject_refresh {
+            let error = serde_json::json!({ "error": "invalid_grant" });
+            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+        }
+        let n = requests.len();
+        Json(serde_json::json!({
+            "access_token": format!("token-{n}"),
+            "expires_in": stub.expires_in.unwrap_or(3600),
+            "refresh_token": format!("refresh-{n}"),
+        }))
+        .into_response()
+    }
+
+    // An SSE server that only accepts the first issued token.
+    async fn events(State(stub): State<Arc<Stub>>, headers: HeaderMap) -> Response {
+        if !authorized(&stub, &headers) {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+        let endpoint = Event::default().event("endpoint").data("/message");
+        let stream = futures::stream::once(async { Ok::<_, Infallible>(endpoint) })
+            .chain(futures::stream::pending());
+        Sse::new(stream).into_response()
+    }
+
+    async fn message(State(stub): State<Arc<Stub>>, headers: HeaderMap) -> StatusCode {
+        if authorized(&stub, &headers) {
+            StatusCode::ACCEPTED
+        } else {
+            StatusCode::UNAUTHORIZED
+        }
+    }
+
+    // A Streamable HTTP endpoint, as seen by the `--remote` probe.
+    async fn mcp(State(stub): State<Arc<Stub>>, headers: HeaderMap) -> Response {
+        if !authorized(&stub, &headers) {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+        Json(serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": {} })).into_response()
+    }
+
+    fn authorized(stub: &Stub, headers: &HeaderMap) -> bool {
+        headers.get("authorization").and_then(|v| v.to_str().ok()) == Some(&stub.issued(1))
+    }
+
+    async fn serve(stub: Arc<Stub>) -> String {
+        let app = Router::new()
+            .route("/token", post(token))
+            .route("/sse", get(events))
+            .route("/message", post(message))
+            .route("/mcp", post(mcp))
+            .with_state(stub);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{addr}")
+    }
+
+    fn client(
+        base: &str,
+        secret: Option<&str>,
+        refresh_token: Option<&str>,
+    ) -> Arc<OAuthClient> {
+        OAuthClient::new(
+            format!("{base}/token"),
+            "gateway".to_string(),
+            secret.map(str::to_string),
+            refresh_token.map(str::to_string),
+            Some("mcp".to_string()),
+        )
+    }
+
+    fn bearer(token: &str) -> HeadersMap {
+        HeadersMap::from([(AUTHORIZATION.to_string(), format!("Bearer {token}"))])
+    }
+
+    // Applies runtime updates the way an output's update loop would.
+    fn apply_updates(runtime: RuntimeArgsStore) -> mpsc::Sender<RuntimeUpdateRequest> {
+        let (tx, mut rx) = mpsc::channel::<RuntimeUpdateRequest>(8);
+        tokio::spawn(async move {
+            while let Some(req) = rx.recv().await {
+                runtime.update_global(req.update.update).await;
+                let _ = req.respond_to.send(RuntimeApplyResult::ok("Updated", false));
+            }
+        });
+        tx
+    }
+
+    #[tokio::test]
+    async fn client_credentials_grant_sets_runtime_header() {
+        let stub = Arc::new(Stub::default());
+        let base = serve(stub.clone()).await;
+        let runtime = RuntimeArgsStore::new(RuntimeArgs {
+            headers: HeadersMap::from([("X-Other".to_string(), "kept".to_string())]),
+            ..Default::default()
+        });
+        let oauth = client(&base, Some("secret"), None);
+        oauth.start(runtime.clone(), apply_updates(runtime.clone())).await.unwrap();
+
+        let headers = runtime.get_effective(None).await.headers;
+        assert_eq!(headers[AUTHORIZATION], stub.issued(1));
+        assert_eq!(headers["X-Other"], "kept");
+        let requests = stub.requests.lock().unwrap();
+        assert_eq!(requests[0].form["grant_type"], "client_credentials");
+        assert_eq!(requests[0].form["scope"], "mcp");
+        assert!(!requests[0].form.contains_key("client_id"));
+        assert!(requests[0].authorization.as_deref().unwrap().starts_with("Basic "));
+    }
+
+    #[tokio::test]
+    async fn unrepresentable_expiry_schedules_no_refresh() {
+        let stub = Arc::new(Stub {
+            expires_in: Some(u64::MAX),
+            ..Default::default()
+        });
+        let base = serve(stub.clone()).await;
+        let oauth = client(&base, Some("secret"), None);
+
+        let headers = oauth.reauthorize(&HeadersMap::new()).await.unwrap();
+        assert_eq!(headers[AUTHORIZATION], stub.issued(1));
+        assert!(oauth.state.lock().await.refresh_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn refresh_token_grant_uses_rotated_token() {
+        let stub = Arc::new(Stub::default());
+        let base = serve(stub.clone()).await;
+        let oauth = client(&base, None, Some("refresh-0"));
+
+        let first = oauth.reauthorize(&HeadersMap::new()).await.unwrap();
+        assert_eq!(first[AUTHORIZATION], stub.issued(1));
+        let second = oauth.reauthorize(&first).await.unwrap();
+        assert_eq!(second[AUTHORIZATION], stub.issued(2));
+
+        let requests = stub.requests.lock().unwrap();
+        let used: Vec<&str> = requests.iter().map(|r| r.form["refresh_token"].as_str()).collect();
+        assert_eq!(used, ["refresh-0", "refresh-1"]);
+        assert!(requests.iter().all(|r| r.authorization.is_none()));
+        assert!(requests.iter().all(|r| r.form["client_id"] == "gateway"));
+    }
+
+    #[tokio::test]
+    async fn rejected_refresh_token_falls_back_to_client_credentials() {
+        let stub = Arc::new(Stub {
+            reject_refresh: true,
+            ..Default::default()
+        });
+        let base = serve(stub.clone()).await;
+
+        let oauth = client(&base, Some("secret"), Some("revoked"));
+        let headers = oauth.reauthorize(&HeadersMap::new()).await.unwrap();
+        assert_eq!(headers[AUTHORIZATION], stub.issued(2));
+        assert_eq!(stub.grants(), ["refresh_token", "client_credentials"]);
+
+        // Without a client secret there is nothing to fall back to.
+        let oauth = client(&base, None, Some("revoked"));
+        assert!(oauth.reauthorize(&HeadersMap::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reauthorize_replaces_only_the_rejected_token() {
+        let stub = Arc::new(Stub::default());
+        let base = serve(stub.clone()).await;
+        let runtime = RuntimeArgsStore::new(RuntimeArgs::default());
+        let oauth = client(&base, Some("secret"), None);
+        oauth.start(runtime.clone(), apply_updates(runtime.clone())).await.unwrap();
+
+        // A request that raced an earlier refresh gets the current token back.
+        let stale = oauth.reauthorize(&bearer("token-0")).await.unwrap();
+        assert_eq!(stale[AUTHORIZATION], stub.issued(1));
+        assert_eq!(stub.grants().len(), 1);
+
+        let renewed = oauth.reauthorize(&bearer("token-1")).await.unwrap();
+        assert_eq!(renewed[AUTHORIZATION], stub.issued(2));
+        let headers = runtime.get_effective(None).await.headers;
+        assert_eq!(headers[AUTHORIZATION], stub.issued(2));
+    }
+
+    #[tokio::test]
+    async fn upstream_401_is_retried_with_a_fresh_token() {
+        let stub = Arc::new(Stub::default());
+        let base = serve(stub.clone()).await;
+        let runtime = RuntimeArgs {
+            headers: bearer("revoked"),
+            ..Default::default()
+        };
+        let sse = SseClient::connect(
+            format!("{base}/sse"),
+            "2024-11-05".to_string(),
+            &runtime,
+            Some(client(&base, Some("secret"), None)),
+        );
+
+        // Both the event stream and the POST start out with a revoked token.
+        let notification = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/initialized",
+        });
+        let sent = tokio::time::timeout(Duration::from_secs(5), sse.send(&notification)).await;
+        assert!(matches!(sent, Ok(Ok(()))));
+        assert_eq!(stub.grants().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn remote_probe_retries_401_with_a_fresh_token() {
+        let stub = Arc::new(Stub::default());
+        let base = serve(stub.clone()).await;
+        let runtime = RuntimeArgs {
+            headers: bearer("expired"),
+            ..Default::default()
+        };
+        let oauth = client(&base, Some("secret"), None);
+
+        let detected =
+            detect_transport(&format!("{base}/mcp"), "2024-11-05", &runtime, Some(&oauth)).await;
+        assert!(matches!(detected, Ok(RemoteTransport::StreamableHttp)));
+        assert_eq!(stub.grants().len(), 1);
+    }
+}
@@ -0,0 +1,188 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use reqwest::Url;
+
+use crate::support::sampling::SamplingBridge;
+use crate::transport::MessageSource;
+
+// Answers requests a child sends to its client when there is no client that
+// could answer them, as in stateless Streamable HTTP.
+pub struct LocalResponder {
+    roots: Vec<serde_json::Value>,
+    sampling: Option<SamplingBridge>,
+}
+
+impl LocalResponder {
+    pub fn new(roots: &[String], sampling: Option<SamplingBridge>) -> Self {
+        Self {
+            roots: roots.iter().map(|root| root_entry(root)).collect(),
+            sampling,
+        }
+    }
+
+    pub fn capabilities(&self) -> serde_json::Value {
+        let mut capabilities = serde_json::json!({});
+        if !self.roots.is_empty() {
+            capabilities["roots"] = serde_json::json!({ "listChanged": false });
+        }
+        if self.sampling.is_some() {
+            capabilities["sampling"] = serde_json::json!({});
+        }
+        capabilities
+    }
+
+    pub fn bridges_sampling(&self, message: &serde_json::Value) -> bool {
+        self.sampling.is_some()
+            && message.get("id").is_some()
+            && message.get("method").and_then(|m| m.as_str()) == Some("sampling/createMessage")
+    }
+
+    // Answers a sampling request from `child` in the background, for outputs
+    // whose clients cannot take it.
+    pub fn answer_sampling(
+        self: &Arc<Self>,
+        child: &Arc<dyn MessageSource>,
+        request: serde_json::Value,
+    ) {
+        let responder = self.clone();
+        let child = child.clone();
+        tokio::spawn(async move {
+            let response = responder.respond(&request).await;
+            if let Err(err) = child.send(&response).await {
+                tracing::error!("Failed to send sampling result to child: {err}");
+            }
+        });
+    }
+
+    pub async fn respond(&self, request: &serde_json::Value) -> serde_json::Value {
+        let id = request.get("id").cloned().unwrap_or(serde_json::Value::Null);
+        let method = request
+            .get("method")
+            .and_then(|m| m.as_str())
+            .unwrap_or_default();
+        match method {
+            "ping" => result(id, serde_json::json!({})),
+            "roots/list" => result(id, serde_json::json!({ "roots": self.roots })),
+            "elicitation/create" => result(id, serde_json::json!({ "action": "decline" })),
+            "sampling/createMessage" => match &self.sampling {
+                Some(bridge) => {
+                    let params = request.get("params").cloned().unwrap_or_default();
+                    match bridge.create_message(&params).await {
+                        Ok(message) => result(id, message),
+                        Err(err) => {
+                            tracing::error!("Sampling bridge failed: {err}");
+                            error(id, -32603, &format!("Sampling request failed: {err}"))
+                        }
+                    }
+                }
+                None => error(
+                    id,
+                    -32601,
+                    "Sampling is not supported by this gateway without --samplingUrl",
+                ),
+            },
+            _ => error(id, -32601, &format!("Method not found: {method}")),
+        }
+    }
+}
+
+fn root_entry(root: &str) -> serde_json::Value {
+    let uri = if root.contains("://") {
+        root.to_string()
+    } else {
+        let path = std::fs::canonicalize(root).unwrap_or_else(|_| {
+            std::env::current_dir()
+                .map(|dir| dir.join(root))
+                .unwrap_or_else(|_| Path::new(root).to_path_buf())
+        });
+        Url::from_file_path(&path)
+            .map(|url| url.to_string())
+            .unwrap_or_else(|_| format!("file://{}", path.display()))
+    };
+    let name = uri
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    serde_json::json!({ "uri": uri, "name": name })
+}
+
+fn result(id: serde_json::Value, result: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error(id: serde_json::Value, code: i64, message: &str) -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn request(method: &str) -> serde_json::Value {
+        json!({ "jsonrpc": "2.0", "id": 7, "method": method, "params": {} })
+    }
+
+    fn bridge() -> SamplingBridge {
+        SamplingBridge::new("http://127.0.0.1:9/v1".to_string(), Vec::new(), None)
+    }
+
+    #[tokio::test]
+    async fn answers_ping_and_elicitation() {
+        let responder = LocalResponder::new(&[], None);
+        assert_eq!(
+            responder.respond(&request("ping")).await,
+            json!({ "jsonrpc": "2.0", "id": 7, "result": {} })
+        );
+        let elicitation = responder.respond(&request("elicitation/create")).await;
+        assert_eq!(elicitation["result"], json!({ "action": "decline" }));
+    }
+
+    #[tokio::test]
+    async fn lists_roots_as_file_uris() {
+        let dir = std::env::temp_dir().canonicalize().unwrap();
+        let project = dir.to_string_lossy().into_owned();
+        let roots = [project, "https://example.com/repo/".to_string()];
+        let responder = LocalResponder::new(&roots, None);
+
+        let listed = responder.respond(&request("roots/list")).await;
+        let expected_uri = Url::from_file_path(&dir).unwrap().to_string();
+        let expected_name = dir.file_name().unwrap().to_string_lossy().into_owned();
+        assert_eq!(
+            listed["result"]["roots"],
+            json!([
+                { "uri": expected_uri, "name": expected_name },
+                { "uri": "https://example.com/repo/", "name": "repo" },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn sampling_without_bridge_is_not_found() {
+        let responder = LocalResponder::new(&[], None);
+        let response = responder.respond(&request("sampling/createMessage")).await;
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["error"]["code"], -32601);
+        assert!(!responder.bridges_sampling(&request("sampling/createMessage")));
+        let unknown = responder.respond(&request("tools/list")).await;
+        assert_eq!(unknown["error"]["code"], -32601);
+    }
+
+    #[test]
+    fn advertises_only_configured_capabilities() {
+        assert_eq!(LocalResponder::new(&[], None).capabilities(), json!({}));
+        let roots = LocalResponder::new(&["/srv".to_string()], None);
+        assert_eq!(roots.capabilities(), json!({ "roots": { "listChanged": false } }));
+        let sampling = LocalResponder::new(&[], Some(bridge()));
+        assert_eq!(sampling.capabilities(), json!({ "sampling": {} }));
+        assert!(sampling.bridges_sampling(&request("sampling/createMessage")));
+    }
+}
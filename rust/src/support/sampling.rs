@@ -0,0 +1,372 @@
+use crate::config::Config;
+
+// Fulfills `sampling/createMessage` through an OpenAI-compatible chat
+// completions endpoint when no downstream client can.
+pub struct SamplingBridge {
+    http: reqwest::Client,
+    url: String,
+    models: Vec<String>,
+    api_key: Option<String>,
+}
+
+impl SamplingBridge {
+    pub fn new(url: String, models: Vec<String>, api_key: Option<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url,
+            models,
+            api_key,
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let url = config.sampling_url.clone()?;
+        Some(Self::new(
+            url,
+            config.sampling_models.clone(),
+            config.sampling_api_key.clone(),
+        ))
+    }
+
+    pub async fn create_message(
+        &self,
+        params: &serde_json::Value,
+    ) -> Result<serde_json::Value, String> {
+        let body = chat_request(params, &self.models)?;
+        let mut req = self.http.post(&self.url).json(&body);
+        if let Some(key) = &self.api_key {
+            req = req.bearer_auth(key);
+        }
+        let resp = req.send().await.map_err(|err| err.to_string())?;
+        let status = resp.status();
+        let text = resp.text().await.map_err(|err| err.to_string())?;
+        if !status.is_success() {
+            return Err(format!("Completion endpoint returned {status}: {text}"));
+        }
+        let json: serde_json::Value =
+            serde_json::from_str(&text).map_err(|err| err.to_string())?;
+        sampling_result(&json, &body["model"])
+    }
+}
+
+fn chat_request(
+    params: &serde_json::Value,
+    models: &[String],
+) -> Result<serde_json::Value, String> {
+    let mut messages = Vec::new();
+    if let Some(system) = params.get("systemPrompt").and_then(|v| v.as_str()) {
+        messages.push(serde_json::json!({ "role": "system", "content": system }));
+    }
+    for message in params
+        .get("messages")
+        .and_then(|v| v.as_array())
+        .ok_or("sampling/createMessage is missing messages")?
+    {
+        let role = message
+            .get("role")
+            .and_then(|v| v.as_str())
+            .unwrap_or("user");
+        let content = message.get("content").unwrap_or(&serde_json::Value::Null);
+        messages.push(serde_json::json!({ "role": role, "content": chat_content(content)? }));
+    }
+
+    let model = select_model(params.get("modelPreferences"), models)
+        .ok_or("No sampling model configured and the request has no model hints")?;
+    let mut body = serde_json::json!({ "model": model, "messages": messages });
+    if let Some(max_tokens) = params.get("maxTokens") {
+        body["max_tokens"] = max_tokens.clone();
+    }
+    if let Some(temperature) = params.get("temperature") {
+        body["temperature"] = temperature.clone();
+    }
+    if let Some(stop) = params.get("stopSequences") {
+        body["stop"] = stop.clone();
+    }
+    Ok(body)
+}
+
+// Hints are matched as substrings of the configured models, in hint order;
+// without configured models the first hint is used verbatim.
+fn select_model(preferences: Option<&serde_json::Value>, models: &[String]) -> Option<String> {
+    let hints: Vec<&str> = preferences
+        .and_then(|p| p.get("hints"))
+        .and_then(|h| h.as_array())
+        .map(|hints| {
+            hints
+                .iter()
+                .filter_map(|hint| hint.get("name").and_then(|n| n.as_str()))
+                .collect()
+        })
+        .unwrap_or_default();
+    for hint in &hints {
+        if let Some(model) = models.iter().find(|model| model.contains(hint)) {
+            return Some(model.clone());
+        }
+    }
+    models
+        .first()
+        .cloned()
+        .or_else(|| hints.first().map(|hint| hint.to_string()))
+}
+
+fn chat_content(content: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let blocks = match content {
+        serde_json::Value::Array(blocks) => blocks.clone(),
+        other => vec![other.clone()],
+    };
+    if let [block] = blocks.as_slice() {
+        if block.get("type").and_then(|t| t.as_str()) == Some("text") {
+            return Ok(block.get("text").cloned().unwrap_or_default());
+        }
+    }
+    let mut parts = Vec::new();
+    for block in &blocks {
+        let kind = block.get("type").and_then(|t| t.as_str()).unwrap_or_default();
+        let data = block.get("data").and_then(|d| d.as_str()).unwrap_or_default();
+        let mime = block
+            .get("mimeType")
+            .and_then(|m| m.as_str())
+            .unwrap_or_default();
+        parts.push(match kind {
+            "text" => serde_json::json!({ "type": "text", "text": block.get("text") }),
+            "image" => serde_json::json!({
+                "type": "image_url",
+                "image_url": { "url": format!("data:{mime};base64,{data}") }
+            }),
+            "audio" => serde_json::json!({
+                "type": "input_audio",
+                "input_audio": {
+                    "data": data,
+                    "format": mime.rsplit('/').next().unwrap_or_default()
+                }
+            }),
+            _ => return Err(format!("Unsupported sampling content type: {kind}")),
+        });
+    }
+    Ok(serde_json::Value::Array(parts))
+}
+
+fn sampling_result(
+    completion: &serde_json::Value,
+    requested_model: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let choice = completion
+        .get("choices")
+        .and_then(|c| c.get(0))
+        .ok_or("Completion response has no choices")?;
+    let text = choice
+        .get("message")
+        .and_then(|m| m.get("content"))
+        .and_then(|c| c.as_str())
+        .unwrap_or_default();
+    let stop_reason = match choice.get("finish_reason").and_then(|r| r.as_str()) {
+        Some("stop") => serde_json::json!("endTurn"),
+        Some("length") => serde_json::json!("maxTokens"),
+        Some(other) => serde_json::json!(other),
+        None => serde_json::Value::Null,
+    };
+    let mut result = serde_json::json!({
+        "role": "assistant",
+        "content": { "type": "text", "text": text },
+        "model": completion.get("model").unwrap_or(requested_model),
+    });
+    if !stop_reason.is_null() {
+        result["stopReason"] = stop_reason;
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::{IntoResponse, Response};
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use serde_json::json;
+
+    use super::*;
+
+    // An OpenAI-compatible endpoint that echoes the requested model and the
+    // bearer token it was called with.
+    async fn completions(headers: HeaderMap, Json(body): Json<serde_json::Value>) -> Response {
+        let token = headers
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        Json(json!({
+            "model": body["model"],
+            "choices": [{ "message": { "content": token }, "finish_reason": "stop" }],
+        }))
+        .into_response()
+    }
+
+    async fn serve() -> String {
+        let app = Router::new()
+            .route("/ok", post(completions))
+            .route("/error", post(|| async { (StatusCode::TOO_MANY_REQUESTS, "slow down") }))
+            .route("/malformed", post(|| async { "not json" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{addr}")
+    }
+
+    fn bridge(url: String) -> SamplingBridge {
+        SamplingBridge {
+            http: reqwest::Client::new(),
+            url,
+            models: models(&["gpt-4o-mini"]),
+            api_key: Some("sk-test".to_string()),
+        }
+    }
+
+    fn params() -> serde_json::Value {
+        json!({ "messages": [{ "role": "user", "content": { "type": "text", "text": "Hi" } }] })
+    }
+
+    fn models(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn maps_messages_and_limits() {
+        let params = json!({
+            "systemPrompt": "Be brief.",
+            "messages": [
+                { "role": "user", "content": { "type": "text", "text": "Hi" } },
+                { "role": "assistant", "content": [{ "type": "text", "text": "Hello" }] },
+            ],
+            "maxTokens": 64,
+            "temperature": 0.2,
+            "stopSequences": ["END"],
+        });
+        let body = chat_request(&params, &models(&["gpt-4o-mini"])).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "model": "gpt-4o-mini",
+                "messages": [
+                    { "role": "system", "content": "Be brief." },
+                    { "role": "user", "content": "Hi" },
+                    { "role": "assistant", "content": "Hello" },
+                ],
+                "max_tokens": 64,
+                "temperature": 0.2,
+                "stop": ["END"],
+            })
+        );
+    }
+
+    #[test]
+    fn omits_unset_limits() {
+        let params = json!({ "messages": [], "modelPreferences": { "hints": [{ "name": "m" }] } });
+        let body = chat_request(&params, &[]).unwrap();
+        assert!(body.get("max_tokens").is_none());
+        assert!(body.get("temperature").is_none());
+        assert!(body.get("stop").is_none());
+    }
+
+    #[test]
+    fn maps_image_and_audio_content() {
+        let content = json!([
+            { "type": "text", "text": "What is this?" },
+            { "type": "image", "data": "aW1n", "mimeType": "image/png" },
+            { "type": "audio", "data": "YXVk", "mimeType": "audio/wav" },
+        ]);
+        assert_eq!(
+            chat_content(&content).unwrap(),
+            json!([
+                { "type": "text", "text": "What is this?" },
+                { "type": "image_url", "image_url": { "url": "data:image/png;base64,aW1n" } },
+                { "type": "input_audio", "input_audio": { "data": "YXVk", "format": "wav" } },
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_content() {
+        let content = json!({ "type": "resource", "resource": {} });
+        assert!(chat_content(&content).is_err());
+        assert!(chat_request(&json!({}), &models(&["m"])).is_err());
+    }
+
+    #[test]
+    fn selects_model_from_hints() {
+        let configured = models(&["gpt-4o-mini", "claude-3-haiku", "claude-3-sonnet"]);
+        let preferences = json!({ "hints": [{ "name": "sonnet" }, { "name": "haiku" }] });
+        assert_eq!(
+            select_model(Some(&preferences), &configured).as_deref(),
+            Some("claude-3-sonnet")
+        );
+
+        let unmatched = json!({ "hints": [{ "name": "llama" }] });
+        assert_eq!(select_model(Some(&unmatched), &configured).as_deref(), Some("gpt-4o-mini"));
+        assert_eq!(select_model(None, &configured).as_deref(), Some("gpt-4o-mini"));
+
+        assert_eq!(select_model(Some(&unmatched), &[]).as_deref(), Some("llama"));
+        assert_eq!(select_model(None, &[]), None);
+        assert!(chat_request(&json!({ "messages": [] }), &[]).is_err());
+    }
+
+    #[test]
+    fn maps_completion_to_result() {
+        let completion = json!({
+            "model": "gpt-4o-mini-2024-07-18",
+            "choices": [{ "message": { "content": "Hello" }, "finish_reason": "stop" }],
+        });
+        assert_eq!(
+            sampling_result(&completion, &json!("gpt-4o-mini")).unwrap(),
+            json!({
+                "role": "assistant",
+                "content": { "type": "text", "text": "Hello" },
+                "model": "gpt-4o-mini-2024-07-18",
+                "stopReason": "endTurn",
+            })
+        );
+    }
+
+    #[test]
+    fn maps_finish_reasons() {
+        let result = |finish_reason: serde_json::Value| {
+            let completion = json!({
+                "choices": [{ "message": { "content": "" }, "finish_reason": finish_reason }],
+            });
+            sampling_result(&completion, &json!("requested")).unwrap()
+        };
+        assert_eq!(result(json!("length"))["stopReason"], "maxTokens");
+        assert_eq!(result(json!("content_filter"))["stopReason"], "content_filter");
+        assert!(result(serde_json::Value::Null).get("stopReason").is_none());
+        assert_eq!(result(json!("stop"))["model"], "requested");
+        assert!(sampling_result(&json!({ "choices": [] }), &json!("m")).is_err());
+    }
+
+    #[tokio::test]
+    async fn create_message_returns_completion() {
+        let base = serve().await;
+        let result = bridge(format!("{base}/ok")).create_message(&params()).await.unwrap();
+        assert_eq!(
+            result,
+            json!({
+                "role": "assistant",
+                "content": { "type": "text", "text": "Bearer sk-test" },
+                "model": "gpt-4o-mini",
+                "stopReason": "endTurn",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn create_message_reports_error_status() {
+        let base = serve().await;
+        let err = bridge(format!("{base}/error")).create_message(&params()).await.unwrap_err();
+        assert!(err.contains("429"), "{err}");
+        assert!(err.contains("slow down"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn create_message_rejects_malformed_body() {
+        let base = serve().await;
+        assert!(bridge(format!("{base}/malformed")).create_message(&params()).await.is_err());
+    }
+}
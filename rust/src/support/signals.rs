@@ -1,9 +1,12 @@
 use std::sync::Arc;
 
+use futures::future::BoxFuture;
 use tokio::signal::unix::{signal, SignalKind};
 
-pub fn install_signal_handlers(cleanup: Option<Arc<dyn Fn() + Send + Sync>>) {
-    let handler = move |name: &'static str, cleanup: Option<Arc<dyn Fn() + Send + Sync>>| {
+pub type Cleanup = Arc<dyn Fn() -> BoxFuture<'static, ()> + Send + Sync>;
+
+pub fn install_signal_handlers(cleanup: Option<Cleanup>) {
+    let handler = move |name: &'static str, cleanup: Option<Cleanup>| {
         tokio::spawn(async move {
             if let Ok(mut sig) = signal(match name {
                 "SIGINT" => SignalKind::interrupt(),
@@ -13,7 +16,7 @@ pub fn install_signal_handlers(cleanup: Option<Arc<dyn Fn() + Send + Sync>>) {
                 sig.recv().await;
                 tracing::info!("Caught {name}. Exiting...");
                 if let Some(cleanup) = cleanup {
-                    cleanup();
+                    cleanup().await;
                 }
                 std::process::exit(0);
             }
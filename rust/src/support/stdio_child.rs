@@ -15,6 +15,17 @@ pub struct CommandSpec {
 }
 
 impl CommandSpec {
+    pub fn parse(cmd: &str) -> Result<Self, String> {
+        let parts = shell_words::split(cmd).map_err(|err| err.to_string())?;
+        if parts.is_empty() {
+            return Err("stdio command is empty".into());
+        }
+        Ok(CommandSpec {
+            program: parts[0].clone(),
+            args: parts[1..].to_vec(),
+        })
+    }
+
     pub fn build_command(&self, runtime: &RuntimeArgs) -> Command {
         let mut cmd = Command::new(&self.program);
         cmd.args(&self.args);
@@ -0,0 +1,372 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
+
+use crate::runtime::store::RuntimeArgsStore;
+use crate::support::jsonrpc::{
+    auto_init_id, create_initialize_request, create_initialized_notification,
+};
+use crate::transport::{MessageSource, SourceSpec};
+
+const INITIALIZE_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct PooledChild {
+    child: Arc<dyn MessageSource>,
+    uses: u64,
+    generation: u64,
+}
+
+// A leased child. Dropping it without `release`, as happens when the request
+// using it is cancelled, shuts the child down since it may still be mid-request.
+pub struct Lease {
+    pool: Arc<ChildPool>,
+    child: Arc<dyn MessageSource>,
+    pooled: Option<PooledChild>,
+}
+
+impl Lease {
+    pub fn source(&self) -> &dyn MessageSource {
+        self.child.as_ref()
+    }
+
+    pub async fn release(mut self, reusable: bool) {
+        if let Some(pooled) = self.pooled.take() {
+            self.pool.put_back(pooled, reusable).await;
+        }
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if let Some(pooled) = self.pooled.take() {
+            let pool = self.pool.clone();
+            tokio::spawn(async move { pool.put_back(pooled, false).await });
+        }
+    }
+}
+
+pub struct ChildPool {
+    source: SourceSpec,
+    runtime: RuntimeArgsStore,
+    protocol_version: String,
+    capabilities: serde_json::Value,
+    size: usize,
+    max_uses: Option<u64>,
+    generation: AtomicU64,
+    filling: AtomicBool,
+    idle: Mutex<Vec<PooledChild>>,
+}
+
+impl ChildPool {
+    pub fn new(
+        source: SourceSpec,
+        runtime: RuntimeArgsStore,
+        protocol_version: String,
+        capabilities: serde_json::Value,
+        size: usize,
+        max_uses: Option<u64>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            source,
+            runtime,
+            protocol_version,
+            capabilities,
+            size,
+            max_uses,
+            generation: AtomicU64::new(0),
+            filling: AtomicBool::new(false),
+            idle: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn fill(self: &Arc<Self>) {
+        if self.filling.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let pool = self.clone();
+        tokio::spawn(async move {
+            while pool.idle.lock().await.len() < pool.size {
+                let pooled = match pool.spawn_warm().await {
+                    Ok(pooled) => pooled,
+                    Err(err) => {
+                        tracing::error!("Failed to pre-warm stateless child: {err}");
+                        break;
+                    }
+                };
+                let mut idle = pool.idle.lock().await;
+                if pooled.generation == pool.generation.load(Ordering::SeqCst)
+                    && idle.len() < pool.size
+                {
+                    idle.push(pooled);
+                } else {
+                    drop(idle);
+                    pooled.child.shutdown().await;
+                }
+            }
+            pool.filling.store(false, Ordering::SeqCst);
+        });
+    }
+
+    pub async fn lease(self: &Arc<Self>) -> Result<Lease, String> {
+        let generation = self.generation.load(Ordering::SeqCst);
+        loop {
+            let candidate = self.idle.lock().await.pop();
+            let Some(pooled) = candidate else {
+                break;
+            };
+            if pooled.generation == generation && pooled.child.is_alive().await {
+                self.fill();
+                return Ok(self.lease_of(pooled));
+            }
+            pooled.child.shutdown().await;
+        }
+        self.fill();
+        let pooled = self.spawn_warm().await?;
+        Ok(self.lease_of(pooled))
+    }
+
+    fn lease_of(self: &Arc<Self>, pooled: PooledChild) -> Lease {
+        Lease {
+            pool: self.clone(),
+            child: pooled.child.clone(),
+            pooled: Some(pooled),
+        }
+    }
+
+    async fn put_back(self: &Arc<Self>, mut pooled: PooledChild, reusable: bool) {
+        pooled.uses += 1;
+        let exhausted = self.max_uses.is_some_and(|max| pooled.uses >= max);
+        if reusable
+            && !exhausted
+            && pooled.generation == self.generation.load(Ordering::SeqCst)
+            && pooled.child.is_alive().await
+        {
+            let mut idle = self.idle.lock().await;
+            if idle.len() < self.size {
+                idle.push(pooled);
+                return;
+            }
+        }
+        pooled.child.shutdown().await;
+        self.fill();
+    }
+
+    pub async fn rebuild(self: &Arc<Self>) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        let stale: Vec<PooledChild> = self.idle.lock().await.drain(..).collect();
+        for pooled in stale {
+            pooled.child.shutdown().await;
+        }
+        self.fill();
+    }
+
+    async fn spawn_warm(&self) -> Result<PooledChild, String> {
+        let generation = self.generation.load(Ordering::SeqCst);
+        let runtime = self.runtime.get_effective(None).await;
+        let child = self.source.open(&runtime, false).await?;
+        let handshake = tokio::time::timeout(
+            INITIALIZE_TIMEOUT,
+            initialize(child.as_ref(), &self.protocol_version, &self.capabilities),
+        )
+        .await
+        .unwrap_or_else(|_| Err("Timed out waiting for initialize response".to_string()));
+        if let Err(err) = handshake {
+            child.shutdown().await;
+            return Err(err);
+        }
+        Ok(PooledChild {
+            child,
+            uses: 0,
+            generation,
+        })
+    }
+}
+
+async fn initialize(
+    upstream: &dyn MessageSource,
+    protocol_version: &str,
+    capabilities: &serde_json::Value,
+) -> Result<(), String> {
+    let mut rx = upstream.subscribe();
+    let init_id = auto_init_id();
+    upstream
+        .send(&create_initialize_request(
+            &init_id,
+            protocol_version,
+            capabilities.clone(),
+        ))
+        .await?;
+
+    let mut liveness = tokio::time::interval(Duration::from_millis(250));
+    loop {
+        let msg = tokio::select! {
+            biased;
+            msg = rx.recv() => match msg {
+                Ok(msg) => msg,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            _ = liveness.tick() => {
+                if upstream.is_alive().await {
+                    continue;
+                }
+                match rx.try_recv() {
+                    Ok(msg) => msg,
+                    Err(_) => break,
+                }
+            }
+        };
+        if msg.get("method").is_some()
+            || msg.get("id").and_then(|id| id.as_str()) != Some(init_id.as_str())
+        {
+            continue;
+        }
+        if let Some(error) = msg.get("error") {
+            return Err(format!("Initialize failed: {error}"));
+        }
+        return upstream.send(&create_initialized_notification()).await;
+    }
+    Err("Child terminated before initialize response".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::support::stdio_child::CommandSpec;
+
+    // Answers initialize and exits when asked to, which is all the pool needs.
+    const SERVER: &str = r#"while read -r line; do
+  case "$line" in
+    *'"method":"initialize"'*)
+      id=$(printf '%s' "$line" | sed 's/.*"id":"\([^"]*\)".*/\1/')
+      printf '{"jsonrpc":"2.0","id":"%s","result":{}}\n' "$id" ;;
+    *'"method":"exit"'*) exit 0 ;;
+  esac
+done"#;
+
+    fn pool(max_uses: Option<u64>) -> Arc<ChildPool> {
+        let source = SourceSpec::Stdio(CommandSpec {
+            program: "sh".to_string(),
+            args: vec!["-c".to_string(), SERVER.to_string()],
+        });
+        ChildPool::new(
+            source,
+            RuntimeArgsStore::default(),
+            "2024-11-05".to_string(),
+            json!({}),
+            1,
+            max_uses,
+        )
+    }
+
+    fn addr(child: &Arc<dyn MessageSource>) -> *const () {
+        Arc::as_ptr(child) as *const ()
+    }
+
+    // Waits for background refills to finish.
+    async fn settle(pool: &Arc<ChildPool>) {
+        for _ in 0..200 {
+            if !pool.filling.load(Ordering::SeqCst) && pool.idle.lock().await.len() == pool.size {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+        panic!("pool did not refill");
+    }
+
+    // Settles, then shuts down the idle children so a released child has room.
+    async fn clear_idle(pool: &Arc<ChildPool>) -> Vec<Arc<dyn MessageSource>> {
+        settle(pool).await;
+        let idle: Vec<PooledChild> = pool.idle.lock().await.drain(..).collect();
+        let mut children = Vec::new();
+        for pooled in idle {
+            pooled.child.shutdown().await;
+            children.push(pooled.child);
+        }
+        children
+    }
+
+    async fn idle_children(pool: &Arc<ChildPool>) -> Vec<*const ()> {
+        pool.idle.lock().await.iter().map(|pooled| addr(&pooled.child)).collect()
+    }
+
+    #[tokio::test]
+    async fn released_child_is_leased_again() {
+        let pool = pool(None);
+        let lease = pool.lease().await.unwrap();
+        let child = lease.child.clone();
+        clear_idle(&pool).await;
+
+        lease.release(true).await;
+        assert_eq!(idle_children(&pool).await, vec![addr(&child)]);
+        let again = pool.lease().await.unwrap();
+        assert_eq!(addr(&again.child), addr(&child));
+        again.release(false).await;
+        assert!(!child.is_alive().await);
+        clear_idle(&pool).await;
+    }
+
+    #[tokio::test]
+    async fn child_is_recycled_after_max_uses() {
+        let pool = pool(Some(2));
+        let lease = pool.lease().await.unwrap();
+        let child = lease.child.clone();
+        clear_idle(&pool).await;
+        lease.release(true).await;
+
+        let lease = pool.lease().await.unwrap();
+        assert_eq!(addr(&lease.child), addr(&child));
+        clear_idle(&pool).await;
+        lease.release(true).await;
+
+        assert!(!child.is_alive().await);
+        settle(&pool).await;
+        assert_ne!(idle_children(&pool).await, vec![addr(&child)]);
+        clear_idle(&pool).await;
+    }
+
+    #[tokio::test]
+    async fn dead_child_is_not_returned_to_the_pool() {
+        let pool = pool(None);
+        let lease = pool.lease().await.unwrap();
+        let child = lease.child.clone();
+        clear_idle(&pool).await;
+
+        child.send(&json!({ "jsonrpc": "2.0", "method": "exit" })).await.unwrap();
+        for _ in 0..200 {
+            if !child.is_alive().await {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        lease.release(true).await;
+        settle(&pool).await;
+        assert_ne!(idle_children(&pool).await, vec![addr(&child)]);
+        clear_idle(&pool).await;
+    }
+
+    #[tokio::test]
+    async fn rebuild_replaces_children_of_the_old_generation() {
+        let pool = pool(None);
+        let lease = pool.lease().await.unwrap();
+        let leased = lease.child.clone();
+        settle(&pool).await;
+        let warm = pool.idle.lock().await[0].child.clone();
+
+        pool.rebuild().await;
+        assert!(!warm.is_alive().await);
+        settle(&pool).await;
+        let rebuilt = idle_children(&pool).await;
+        assert_ne!(rebuilt, vec![addr(&warm)]);
+
+        lease.release(true).await;
+        assert!(!leased.is_alive().await);
+        assert_eq!(idle_children(&pool).await, rebuilt);
+        clear_idle(&pool).await;
+    }
+}
@@ -0,0 +1,224 @@
+pub mod child_pool;
+pub mod mount;
+pub mod multi_server;
+pub mod remote;
+pub mod shared_child;
+pub mod sse_client;
+pub mod sse_server;
+pub mod stdio_server;
+pub mod streamable_http_client;
+pub mod streamable_http_server;
+pub mod ws_client;
+pub mod ws_server;
+
+use std::fmt;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use tokio::sync::{broadcast, mpsc, OnceCell};
+
+use crate::config::{Config, OutputTransport};
+use crate::runtime::store::RuntimeArgsStore;
+use crate::runtime::RuntimeUpdateRequest;
+use crate::support::oauth_client::OAuthClient;
+use crate::support::stdio_child::{CommandSpec, StdioChild};
+use crate::transport::multi_server::MultiServer;
+use crate::transport::remote::{detect_transport, RemoteTransport};
+use crate::transport::sse_client::SseClient;
+use crate::transport::sse_server::SseServer;
+use crate::transport::stdio_server::StdioServer;
+use crate::transport::streamable_http_client::StreamableHttpClient;
+use crate::transport::streamable_http_server::StreamableHttpServer;
+use crate::transport::ws_client::WsClient;
+use crate::transport::ws_server::WsServer;
+use crate::types::RuntimeArgs;
+
+pub trait MessageSource: Send + Sync {
+    fn send<'a>(&'a self, message: &'a serde_json::Value) -> BoxFuture<'a, Result<(), String>>;
+    fn subscribe(&self) -> broadcast::Receiver<serde_json::Value>;
+    fn is_alive(&self) -> BoxFuture<'_, bool>;
+    fn restart<'a>(&'a self, runtime: &'a RuntimeArgs) -> BoxFuture<'a, Result<(), String>>;
+    fn shutdown(&self) -> BoxFuture<'_, ()>;
+}
+
+pub trait MessageSink: Send {
+    fn serve(
+        self: Box<Self>,
+        source: SourceSpec,
+        runtime: RuntimeArgsStore,
+        updates: mpsc::Receiver<RuntimeUpdateRequest>,
+    ) -> BoxFuture<'static, Result<(), String>>;
+}
+
+pub fn build_sink(config: Config) -> Box<dyn MessageSink> {
+    if config.output_transports.len() > 1 {
+        return Box::new(MultiServer::new(config));
+    }
+    match config.output_transport {
+        OutputTransport::Stdio => Box::new(StdioServer::new(config)),
+        OutputTransport::Sse => Box::new(SseServer::new(config)),
+        OutputTransport::Ws => Box::new(WsServer::new(config)),
+        OutputTransport::StreamableHttp => Box::new(StreamableHttpServer::new(config)),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SourceSpec {
+    Stdio(CommandSpec),
+    Sse {
+        url: String,
+        protocol_version: String,
+        oauth: Option<Arc<OAuthClient>>,
+    },
+    StreamableHttp {
+        url: String,
+        protocol_version: String,
+        oauth: Option<Arc<OAuthClient>>,
+    },
+    Ws { url: String },
+    Remote {
+        url: String,
+        protocol_version: String,
+        detected: Arc<OnceCell<RemoteTransport>>,
+        oauth: Option<Arc<OAuthClient>>,
+    },
+}
+
+impl SourceSpec {
+    pub fn from_config(config: &Config, oauth: Option<Arc<OAuthClient>>) -> Result<Self, String> {
+        if let Some(cmd) = &config.stdio {
+            return Ok(SourceSpec::Stdio(CommandSpec::parse(cmd)?));
+        }
+        if let Some(url) = &config.sse {
+            return Ok(SourceSpec::Sse {
+                url: url.clone(),
+                protocol_version: config.protocol_version.clone(),
+                oauth,
+            });
+        }
+        if let Some(url) = &config.streamable_http {
+            return Ok(SourceSpec::StreamableHttp {
+                url: url.clone(),
+                protocol_version: config.protocol_version.clone(),
+                oauth,
+            });
+        }
+        if let Some(url) = &config.ws {
+            return Ok(SourceSpec::Ws { url: url.clone() });
+        }
+        if let Some(url) = &config.remote {
+            return Ok(SourceSpec::Remote {
+                url: url.clone(),
+                protocol_version: config.protocol_version.clone(),
+                detected: Arc::new(OnceCell::new()),
+                oauth,
+            });
+        }
+        Err("Invalid input transport".to_string())
+    }
+
+    // Remote sources are connections to another MCP server rather than a
+    // local child.
+    pub fn is_remote(&self) -> bool {
+        !matches!(self, SourceSpec::Stdio(_))
+    }
+
+    pub async fn open(
+        &self,
+        runtime: &RuntimeArgs,
+        exit_on_close: bool,
+    ) -> Result<Arc<dyn MessageSource>, String> {
+        match self {
+            SourceSpec::Stdio(spec) => {
+                let child = Arc::new(StdioChild::new(spec.clone(), exit_on_close));
+                child.spawn(runtime).await?;
+                Ok(child)
+            }
+            SourceSpec::Sse {
+                url,
+                protocol_version,
+                oauth,
+            } => Ok(SseClient::connect(
+                url.clone(),
+                protocol_version.clone(),
+                runtime,
+                oauth.clone(),
+            )),
+            SourceSpec::StreamableHttp {
+                url,
+                protocol_version,
+                oauth,
+            } => Ok(StreamableHttpClient::connect(
+                url.clone(),
+                protocol_version.clone(),
+                runtime,
+                oauth.clone(),
+            )),
+            SourceSpec::Ws { url } => Ok(WsClient::connect(url.clone(), runtime)),
+            SourceSpec::Remote {
+                url,
+                protocol_version,
+                detected,
+                oauth,
+            } => {
+                let transport = detected
+                    .get_or_try_init(|| {
+                        detect_transport(url, protocol_version, runtime, oauth.as_deref())
+                    })
+                    .await?;
+                Ok(match transport {
+                    RemoteTransport::StreamableHttp => StreamableHttpClient::connect(
+                        url.clone(),
+                        protocol_version.clone(),
+                        runtime,
+                        oauth.clone(),
+                    ),
+                    RemoteTransport::Sse => SseClient::connect(
+                        url.clone(),
+                        protocol_version.clone(),
+                        runtime,
+                        oauth.clone(),
+                    ),
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for SourceSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceSpec::Stdio(spec) => {
+                let mut parts = vec![spec.program.as_str()];
+                parts.extend(spec.args.iter().map(|arg| arg.as_str()));
+                write!(f, "stdio: {}", shell_words::join(parts))
+            }
+            SourceSpec::Sse { url, .. } => write!(f, "sse: {url}"),
+            SourceSpec::StreamableHttp { url, .. } => write!(f, "streamableHttp: {url}"),
+            SourceSpec::Ws { url } => write!(f, "ws: {url}"),
+            SourceSpec::Remote { url, .. } => write!(f, "remote: {url}"),
+        }
+    }
+}
+
+impl MessageSource for StdioChild {
+    fn send<'a>(&'a self, message: &'a serde_json::Value) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(StdioChild::send(self, message))
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<serde_json::Value> {
+        StdioChild::subscribe(self)
+    }
+
+    fn is_alive(&self) -> BoxFuture<'_, bool> {
+        Box::pin(StdioChild::is_alive(self))
+    }
+
+    fn restart<'a>(&'a self, runtime: &'a RuntimeArgs) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(StdioChild::restart(self, runtime))
+    }
+
+    fn shutdown(&self) -> BoxFuture<'_, ()> {
+        Box::pin(StdioChild::shutdown(self))
+    }
+}
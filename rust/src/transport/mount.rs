@@ -0,0 +1,176 @@
+use std::sync::Arc;
+
+use axum::middleware;
+use axum::response::Response;
+use axum::routing::get;
+use axum::{Json, Router};
+use futures::future::BoxFuture;
+use tokio::sync::mpsc;
+
+use crate::config::Config;
+use crate::runtime::store::{RuntimeArgsStore, RuntimeArgsUpdate, UpdateResult};
+use crate::runtime::{RuntimeApplyResult, RuntimeScope, RuntimeUpdateRequest};
+use crate::support::auth::{require_auth, PROTECTED_RESOURCE_PATH};
+use crate::support::cors::build_cors_layer;
+use crate::support::signals::install_signal_handlers;
+use crate::transport::shared_child::SharedChild;
+
+pub type HealthCheck = Arc<dyn Fn() -> BoxFuture<'static, Response> + Send + Sync>;
+
+// How an output picks up runtime arg changes. The store is updated once by
+// `serve_mounts`, so outputs sharing a listener never apply an update twice.
+pub trait RuntimeTarget: Send + Sync {
+    // Restarts whatever this output runs per session; None when nothing changed.
+    fn apply_global<'a>(
+        &'a self,
+        update: &'a UpdateResult,
+    ) -> BoxFuture<'a, Option<RuntimeApplyResult>>;
+
+    // None when the session does not belong to this output.
+    fn apply_session<'a>(
+        &'a self,
+        session_id: &'a str,
+        update: RuntimeArgsUpdate,
+    ) -> BoxFuture<'a, Option<RuntimeApplyResult>>;
+}
+
+// One HTTP-facing output, ready to be served alone or next to others.
+pub struct Mount {
+    pub router: Router,
+    pub health: HealthCheck,
+    pub runtime: Arc<dyn RuntimeTarget>,
+}
+
+pub async fn serve_mounts(
+    config: &Config,
+    mounts: Vec<Mount>,
+    shared: Option<Arc<SharedChild>>,
+    runtime: RuntimeArgsStore,
+    updates: mpsc::Receiver<RuntimeUpdateRequest>,
+) -> Result<(), String> {
+    let mut router = Router::new();
+    let mut checks = Vec::new();
+    let mut targets = Vec::new();
+    for mount in mounts {
+        router = router.merge(mount.router);
+        checks.push(mount.health);
+        targets.push(mount.runtime);
+    }
+
+    tokio::spawn(apply_updates(shared, targets, runtime, updates));
+
+    // Health and metadata endpoints are added after the auth layer so they
+    // stay open.
+    if let Some(auth) = &config.auth {
+        tracing::info!("  - auth: {auth}");
+        router = router.route_layer(middleware::from_fn_with_state(auth.clone(), require_auth));
+        if let Some(metadata) = auth.resource_metadata() {
+            router = router.route(
+                PROTECTED_RESOURCE_PATH,
+                get(move || async move { Json(metadata) }),
+            );
+        }
+    }
+
+    for ep in &config.health_endpoints {
+        let checks = checks.clone();
+        router = router.route(
+            ep,
+            get(move || async move { health_handler(&checks).await }),
+        );
+    }
+
+    if let Some(cors) = build_cors_layer(&config.cors) {
+        router = router.layer(cors);
+    }
+
+    install_signal_handlers(None);
+
+    let addr: std::net::SocketAddr = ([0, 0, 0, 0], config.port).into();
+    tracing::info!("Listening on port {}", config.port);
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|err| err.to_string())?;
+    axum::serve(listener, router.into_make_service())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+// Healthy only when every mounted output is.
+async fn health_handler(checks: &[HealthCheck]) -> Response {
+    let mut last = None;
+    for check in checks {
+        let response = check().await;
+        if !response.status().is_success() {
+            return response;
+        }
+        last = Some(response);
+    }
+    last.unwrap_or_default()
+}
+
+async fn apply_updates(
+    shared: Option<Arc<SharedChild>>,
+    targets: Vec<Arc<dyn RuntimeTarget>>,
+    runtime: RuntimeArgsStore,
+    mut updates: mpsc::Receiver<RuntimeUpdateRequest>,
+) {
+    while let Some(req) = updates.recv().await {
+        let result = match req.update.scope {
+            RuntimeScope::Global => {
+                let update_result = runtime.update_global(req.update.update).await;
+                let mut results = Vec::new();
+                if let Some(shared) = shared
+                    .as_ref()
+                    .filter(|shared| update_result.restarts(shared.is_remote()))
+                {
+                    let args = runtime.get_effective(None).await;
+                    results.push(match shared.child().restart(&args).await {
+                        Err(_) => RuntimeApplyResult::error("Failed to restart child"),
+                        Ok(()) if update_result.restart_needed => {
+                            RuntimeApplyResult::ok("Restarted child with new runtime args", true)
+                        }
+                        Ok(()) => RuntimeApplyResult::ok("Updated remote server headers", false),
+                    });
+                }
+                for target in &targets {
+                    results.extend(target.apply_global(&update_result).await);
+                }
+                combine(results)
+            }
+            RuntimeScope::Session(session_id) => {
+                let mut result = None;
+                for target in &targets {
+                    result = target
+                        .apply_session(&session_id, req.update.update.clone())
+                        .await;
+                    if result.is_some() {
+                        break;
+                    }
+                }
+                result.unwrap_or_else(|| {
+                    RuntimeApplyResult::error(format!("Unknown session {session_id}"))
+                })
+            }
+        };
+        let _ = req.respond_to.send(result);
+    }
+}
+
+fn combine(mut results: Vec<RuntimeApplyResult>) -> RuntimeApplyResult {
+    if let Some(pos) = results.iter().position(|r| r.status != "ok") {
+        return results.swap_remove(pos);
+    }
+    if results.len() <= 1 {
+        return results
+            .pop()
+            .unwrap_or_else(|| RuntimeApplyResult::ok("Updated runtime args", false));
+    }
+    let restart = results.iter().any(|r| r.restart);
+    let message = results
+        .iter()
+        .map(|r| r.message.as_str())
+        .collect::<Vec<_>>()
+        .join("; ");
+    RuntimeApplyResult::ok(message, restart)
+}
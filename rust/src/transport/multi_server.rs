@@ -0,0 +1,86 @@
+use futures::future::BoxFuture;
+use tokio::sync::mpsc;
+
+use crate::config::{Config, OutputTransport};
+use crate::runtime::store::RuntimeArgsStore;
+use crate::runtime::RuntimeUpdateRequest;
+use crate::transport::mount::serve_mounts;
+use crate::transport::shared_child::SharedChild;
+use crate::transport::{sse_server, streamable_http_server, ws_server};
+use crate::transport::{MessageSink, SourceSpec};
+
+// Serves several output transports on one port so clients can move between
+// them without a second gateway. SSE and WS share one child, routed by a
+// single `SharedChild`, unless they run a child per session/connection; when
+// they do share, Streamable HTTP sessions join them as clients of that child.
+pub struct MultiServer {
+    config: Config,
+}
+
+impl MultiServer {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+}
+
+impl MessageSink for MultiServer {
+    fn serve(
+        self: Box<Self>,
+        source: SourceSpec,
+        runtime: RuntimeArgsStore,
+        updates: mpsc::Receiver<RuntimeUpdateRequest>,
+    ) -> BoxFuture<'static, Result<(), String>> {
+        Box::pin(run(self.config, source, runtime, updates))
+    }
+}
+
+async fn run(
+    config: Config,
+    source: SourceSpec,
+    runtime: RuntimeArgsStore,
+    updates: mpsc::Receiver<RuntimeUpdateRequest>,
+) -> Result<(), String> {
+    tracing::info!(
+        "  - Headers: {}",
+        serde_json::to_string(&config.headers).unwrap_or_else(|_| "(none)".into())
+    );
+    tracing::info!("  - port: {}", config.port);
+    tracing::info!("  - {source}");
+
+    let outputs = &config.output_transports;
+    let shares_child = (outputs.contains(&OutputTransport::Sse) && !config.sse_child_per_session)
+        || (outputs.contains(&OutputTransport::Ws) && !config.ws_child_per_connection);
+    let shared = if shares_child {
+        Some(SharedChild::open(&config, &source, &runtime).await?)
+    } else {
+        None
+    };
+
+    let mut mounts = Vec::new();
+    for output in outputs {
+        mounts.push(match output {
+            OutputTransport::Sse => sse_server::mount(
+                &config,
+                source.clone(),
+                runtime.clone(),
+                shared.clone().filter(|_| !config.sse_child_per_session),
+            ),
+            OutputTransport::Ws => ws_server::mount(
+                &config,
+                source.clone(),
+                runtime.clone(),
+                shared.clone().filter(|_| !config.ws_child_per_connection),
+            ),
+            OutputTransport::StreamableHttp => streamable_http_server::mount(
+                &config,
+                source.clone(),
+                runtime.clone(),
+                shared.clone(),
+            ),
+            OutputTransport::Stdio => {
+                return Err("stdio output cannot be combined with other outputs".to_string())
+            }
+        });
+    }
+    serve_mounts(&config, mounts, shared, runtime, updates).await
+}
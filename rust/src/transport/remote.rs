@@ -0,0 +1,66 @@
+use reqwest::StatusCode;
+
+use crate::support::jsonrpc::{auto_init_id, client_capabilities, create_initialize_request};
+use crate::support::oauth_client::{send_authorized, OAuthClient};
+use crate::types::RuntimeArgs;
+
+#[derive(Debug, Clone, Copy)]
+pub enum RemoteTransport {
+    StreamableHttp,
+    Sse,
+}
+
+// Follows the MCP backwards-compatibility guidance: POST an initialize to the
+// URL and, if the server rejects it with a 4xx, treat the URL as a legacy
+// HTTP+SSE endpoint.
+pub async fn detect_transport(
+    url: &str,
+    protocol_version: &str,
+    runtime: &RuntimeArgs,
+    oauth: Option<&OAuthClient>,
+) -> Result<RemoteTransport, String> {
+    let http = reqwest::Client::new();
+    let probe = create_initialize_request(&auto_init_id(), protocol_version, client_capabilities());
+    let response = send_authorized(oauth, &runtime.headers, |headers| {
+        let mut req = http
+            .post(url)
+            .header("Accept", "application/json, text/event-stream")
+            .json(&probe);
+        for (k, v) in &headers {
+            req = req.header(k, v);
+        }
+        async move { req.send().await.map_err(|err| format!("Failed to reach {url}: {err}")) }
+    })
+    .await?;
+    let status = response.status();
+
+    if status.is_success() {
+        tracing::info!("Remote server at {url} speaks Streamable HTTP");
+        // The probe opened a session nobody will use.
+        if let Some(sid) = response
+            .headers()
+            .get("Mcp-Session-Id")
+            .and_then(|v| v.to_str().ok())
+        {
+            let _ = send_authorized(oauth, &runtime.headers, |headers| {
+                let mut req = http.delete(url).header("Mcp-Session-Id", sid);
+                for (k, v) in &headers {
+                    req = req.header(k, v);
+                }
+                async move { req.send().await.map_err(|err| err.to_string()) }
+            })
+            .await;
+        }
+        return Ok(RemoteTransport::StreamableHttp);
+    }
+    if status.is_client_error()
+        && status != StatusCode::UNAUTHORIZED
+        && status != StatusCode::FORBIDDEN
+    {
+        tracing::info!(
+            "Remote server at {url} rejected Streamable HTTP ({status}), falling back to legacy SSE"
+        );
+        return Ok(RemoteTransport::Sse);
+    }
+    Err(format!("Remote server at {url} answered initialize with {status}"))
+}
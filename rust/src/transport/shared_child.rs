@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::config::Config;
+use crate::runtime::store::RuntimeArgsStore;
+use crate::support::id_rewriter::IdRewriter;
+use crate::support::responder::LocalResponder;
+use crate::support::sampling::SamplingBridge;
+use crate::transport::{MessageSource, SourceSpec};
+use crate::types::RuntimeArgs;
+
+// Hands a child message to one client; false once the client is gone.
+pub type Deliver = Arc<dyn Fn(serde_json::Value) -> BoxFuture<'static, bool> + Send + Sync>;
+
+// One child shared by every client of every output on the listener. Request
+// ids from all clients are rewritten into a single id space, so a response
+// reaches only the client that sent the request, as does progress for it;
+// requests from the child go to the sampling bridge or the most recently
+// active client, and other notifications go to every client.
+pub struct SharedChild {
+    child: Arc<dyn MessageSource>,
+    remote: bool,
+    ids: IdRewriter,
+    clients: Mutex<HashMap<String, Deliver>>,
+    last_active: Mutex<Option<String>>,
+    responder: Arc<LocalResponder>,
+}
+
+impl SharedChild {
+    pub async fn open(
+        config: &Config,
+        source: &SourceSpec,
+        runtime: &RuntimeArgsStore,
+    ) -> Result<Arc<Self>, String> {
+        let initial_args = runtime.get_effective(None).await;
+        let child = source.open(&initial_args, true).await?;
+        let shared = Arc::new(Self {
+            child,
+            remote: source.is_remote(),
+            ids: IdRewriter::default(),
+            clients: Mutex::new(HashMap::new()),
+            last_active: Mutex::new(None),
+            responder: Arc::new(LocalResponder::new(&[], SamplingBridge::from_config(config))),
+        });
+        tokio::spawn(route(shared.clone()));
+        Ok(shared)
+    }
+
+    pub fn child(&self) -> Arc<dyn MessageSource> {
+        self.child.clone()
+    }
+
+    pub fn is_remote(&self) -> bool {
+        self.remote
+    }
+
+    pub async fn register(&self, client_id: &str, deliver: Deliver) {
+        self.clients.lock().await.insert(client_id.to_string(), deliver);
+    }
+
+    pub async fn unregister(&self, client_id: &str) {
+        self.clients.lock().await.remove(client_id);
+        self.ids.forget_session(client_id).await;
+        let mut last_active = self.last_active.lock().await;
+        if last_active.as_deref() == Some(client_id) {
+            *last_active = None;
+        }
+    }
+
+    // Registers a client behind a `MessageSource` of its own, for outputs that
+    // otherwise drive a child directly.
+    pub async fn attach(self: &Arc<Self>, client_id: &str) -> Arc<dyn MessageSource> {
+        let (sender, _) = broadcast::channel(256);
+        let tx = sender.clone();
+        let deliver: Deliver = Arc::new(move |msg| {
+            let _ = tx.send(msg);
+            Box::pin(async { true })
+        });
+        self.register(client_id, deliver).await;
+        Arc::new(SharedClient {
+            shared: self.clone(),
+            client_id: client_id.to_string(),
+            sender,
+        })
+    }
+
+    pub async fn send(&self, client_id: &str, mut message: serde_json::Value) -> Result<(), String> {
+        if !self.ids.rewrite_client_message(client_id, &mut message).await {
+            tracing::debug!("Dropping cancellation from {client_id} for a request it does not own");
+            return Ok(());
+        }
+        *self.last_active.lock().await = Some(client_id.to_string());
+        self.child.send(&message).await
+    }
+
+    async fn deliver(&self, client_id: &str, msg: serde_json::Value) {
+        let deliver = self.clients.lock().await.get(client_id).cloned();
+        let Some(deliver) = deliver else {
+            tracing::debug!("Dropping child message for closed client {client_id}");
+            return;
+        };
+        if !deliver(msg).await {
+            self.unregister(client_id).await;
+        }
+    }
+}
+
+// One client of the shared child seen as a source. Shutting it down only
+// detaches the client; the child itself is restarted by `serve_mounts`.
+struct SharedClient {
+    shared: Arc<SharedChild>,
+    client_id: String,
+    sender: broadcast::Sender<serde_json::Value>,
+}
+
+impl MessageSource for SharedClient {
+    fn send<'a>(&'a self, message: &'a serde_json::Value) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(self.shared.send(&self.client_id, message.clone()))
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<serde_json::Value> {
+        self.sender.subscribe()
+    }
+
+    fn is_alive(&self) -> BoxFuture<'_, bool> {
+        self.shared.child.is_alive()
+    }
+
+    fn restart<'a>(&'a self, _runtime: &'a RuntimeArgs) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn shutdown(&self) -> BoxFuture<'_, ()> {
+        Box::pin(self.shared.unregister(&self.client_id))
+    }
+}
+
+// Where one client's messages go: the shared child, under the client's id,
+// or a child of its own.
+pub enum Upstream {
+    Shared(Arc<SharedChild>, String),
+    Own(Arc<dyn MessageSource>),
+}
+
+impl Upstream {
+    pub async fn send(&self, message: serde_json::Value) -> Result<(), String> {
+        match self {
+            Upstream::Shared(shared, client_id) => shared.send(client_id, message).await,
+            Upstream::Own(child) => child.send(&message).await,
+        }
+    }
+}
+
+async fn route(shared: Arc<SharedChild>) {
+    let mut rx = shared.child.subscribe();
+    loop {
+        let mut msg = match rx.recv().await {
+            Ok(msg) => msg,
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::error!("Shared child router lagged, dropped {skipped} child messages");
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+
+        if let Some(client_id) = shared.ids.restore_response(&mut msg).await {
+            shared.deliver(&client_id, msg).await;
+            continue;
+        }
+        if msg.get("method").is_none() {
+            tracing::debug!("Dropping child response with unknown id: {msg}");
+            continue;
+        }
+        if msg.get("method").and_then(|m| m.as_str()) == Some("notifications/progress") {
+            match shared.ids.restore_progress(&mut msg).await {
+                Some(client_id) => shared.deliver(&client_id, msg).await,
+                None => tracing::debug!("Dropping progress with unknown token: {msg}"),
+            }
+            continue;
+        }
+        if shared.responder.bridges_sampling(&msg) {
+            shared.responder.answer_sampling(&shared.child, msg);
+            continue;
+        }
+        if msg.get("id").is_some() {
+            let target = shared.last_active.lock().await.clone();
+            match target {
+                Some(client_id) => shared.deliver(&client_id, msg).await,
+                None => tracing::error!("No client to forward child request: {msg}"),
+            }
+            continue;
+        }
+
+        let clients: Vec<(String, Deliver)> = shared
+            .clients
+            .lock()
+            .await
+            .iter()
+            .map(|(id, deliver)| (id.clone(), deliver.clone()))
+            .collect();
+        for (client_id, deliver) in clients {
+            if !deliver(msg.clone()).await {
+                shared.unregister(&client_id).await;
+            }
+        }
+    }
+}
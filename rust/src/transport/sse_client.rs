@@ -0,0 +1,470 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use eventsource_stream::Eventsource;
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use reqwest::Url;
+use tokio::sync::{broadcast, oneshot, watch, Mutex, RwLock};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::support::backoff::Backoff;
+use crate::support::correlator::Correlator;
+use crate::support::jsonrpc::{
+    auto_init_id, client_capabilities, create_initialize_request, create_initialized_notification,
+    error_payload, is_initialize_request, is_request, is_response, wrap_response,
+};
+use crate::support::oauth_client::{send_authorized, OAuthClient};
+use crate::transport::MessageSource;
+use crate::types::{HeadersMap, RuntimeArgs};
+
+const INITIALIZE_TIMEOUT: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Where a response arriving on the event stream goes: to subscribers, or to a
+// handshake the client is running itself.
+enum Waiter {
+    Forward,
+    Internal(oneshot::Sender<serde_json::Value>),
+}
+
+// The state of the event stream. An open stream carries its message endpoint,
+// numbered so a session initialized on an earlier connection is not mistaken
+// for a live one.
+#[derive(Clone)]
+enum Link {
+    Connecting,
+    Open(Url, u64),
+    // Reconnecting has failed for long enough that senders are failed rather
+    // than kept waiting.
+    Down,
+}
+
+// Whether the session on a given connection has been initialized, so only
+// one sender runs the handshake and the rest wait for it without a lock.
+#[derive(Clone, Copy, PartialEq)]
+enum Handshake {
+    Idle,
+    Running(u64),
+    Done(u64),
+}
+
+pub struct SseClient {
+    http: reqwest::Client,
+    protocol_version: String,
+    headers: Arc<RwLock<HeadersMap>>,
+    oauth: Option<Arc<OAuthClient>>,
+    link: Arc<watch::Sender<Link>>,
+    handshake: watch::Sender<Handshake>,
+    last_initialize: Mutex<Option<serde_json::Value>>,
+    pending: Arc<Correlator<Waiter>>,
+    sender: broadcast::Sender<serde_json::Value>,
+    stream_task: JoinHandle<()>,
+}
+
+impl SseClient {
+    pub fn connect(
+        sse_url: String,
+        protocol_version: String,
+        runtime: &RuntimeArgs,
+        oauth: Option<Arc<OAuthClient>>,
+    ) -> Arc<Self> {
+        let (sender, _) = broadcast::channel(256);
+        let link = Arc::new(watch::Sender::new(Link::Connecting));
+        let headers = Arc::new(RwLock::new(runtime.headers.clone()));
+        let pending: Arc<Correlator<Waiter>> = Arc::new(Correlator::default());
+        let stream_task = tokio::spawn(stream_events(
+            sse_url,
+            headers.clone(),
+            link.clone(),
+            pending.clone(),
+            sender.clone(),
+            oauth.clone(),
+        ));
+        Arc::new(Self {
+            http: reqwest::Client::new(),
+            protocol_version,
+            headers,
+            oauth,
+            link,
+            handshake: watch::Sender::new(Handshake::Idle),
+            last_initialize: Mutex::new(None),
+            pending,
+            sender,
+            stream_task,
+        })
+    }
+
+    pub async fn send(&self, message: &serde_json::Value) -> Result<(), String> {
+        let headers = self.headers.read().await.clone();
+        if !is_request(message) {
+            let (endpoint, _) = self.endpoint().await?;
+            return self.post(&endpoint, &headers, message).await.map(|_| ());
+        }
+
+        let (endpoint, connection) = match self.endpoint().await {
+            Ok(endpoint) => endpoint,
+            Err(err) => {
+                let _ = self
+                    .sender
+                    .send(wrap_response(message, error_payload(-32000, err)));
+                return Ok(());
+            }
+        };
+        if is_initialize_request(message) {
+            *self.last_initialize.lock().await = Some(message.clone());
+            self.handshake.send_replace(Handshake::Running(connection));
+            let response = self.call(&endpoint, &headers, message).await;
+            // The client follows up with its own initialized notification.
+            self.handshake.send_replace(if response.get("error").is_none() {
+                Handshake::Done(connection)
+            } else {
+                Handshake::Idle
+            });
+            let _ = self.sender.send(response);
+            return Ok(());
+        }
+        if let Err(init_response) = self.ensure_initialized(&endpoint, connection, &headers).await {
+            let _ = self.sender.send(wrap_response(message, init_response));
+            return Ok(());
+        }
+
+        let id = message.get("id").cloned().unwrap_or(serde_json::Value::Null);
+        let key = match self.pending.register(&id, Waiter::Forward).await {
+            Ok(key) => key,
+            Err(err) => {
+                let _ = self
+                    .sender
+                    .send(wrap_response(message, error_payload(-32600, err)));
+                return Ok(());
+            }
+        };
+        match self.post(&endpoint, &headers, message).await {
+            Ok(None) => {
+                // The connection may have dropped after outstanding requests
+                // were failed but before this one was posted.
+                let current = matches!(*self.link.borrow(), Link::Open(_, c) if c == connection);
+                if !current && self.pending.cancel(&key).await.is_some() {
+                    let _ = self.sender.send(connection_lost(&id));
+                }
+            }
+            Ok(Some(payload)) => {
+                dispatch(&self.pending, &self.sender, wrap_response(message, payload)).await;
+            }
+            Err(err) => {
+                self.pending.cancel(&key).await;
+                let _ = self
+                    .sender
+                    .send(wrap_response(message, error_payload(-32000, err)));
+            }
+        }
+        Ok(())
+    }
+
+    async fn endpoint(&self) -> Result<(Url, u64), String> {
+        let mut link = self.link.subscribe();
+        let ready = link.wait_for(|link| !matches!(link, Link::Connecting));
+        let endpoint = match tokio::time::timeout(CONNECT_TIMEOUT, ready).await {
+            Ok(Ok(link)) => match &*link {
+                Link::Open(endpoint, connection) => Ok((endpoint.clone(), *connection)),
+                _ => Err("SSE connection unavailable".to_string()),
+            },
+            _ => Err("Timed out waiting for the SSE connection".to_string()),
+        };
+        endpoint
+    }
+
+    // Each SSE connection is a new server session, so after a reconnect the
+    // client's own initialize is replayed. One sender runs the handshake and
+    // any others wait for its outcome.
+    async fn ensure_initialized(
+        &self,
+        endpoint: &Url,
+        connection: u64,
+        headers: &HeadersMap,
+    ) -> Result<(), serde_json::Value> {
+        let mut handshake = self.handshake.subscribe();
+        loop {
+            let mut claimed = false;
+            self.handshake.send_if_modified(|state| match state {
+                Handshake::Running(c) | Handshake::Done(c) if *c == connection => false,
+                _ => {
+                    *state = Handshake::Running(connection);
+                    claimed = true;
+                    true
+                }
+            });
+            if claimed {
+                let result = self.initialize(endpoint, headers).await;
+                self.handshake.send_replace(match result {
+                    Ok(()) => Handshake::Done(connection),
+                    Err(_) => Handshake::Idle,
+                });
+                return result;
+            }
+            if *handshake.borrow_and_update() == Handshake::Done(connection) {
+                return Ok(());
+            }
+            if handshake.changed().await.is_err() {
+                return Err(error_payload(-32000, "SSE client closed"));
+            }
+        }
+    }
+
+    async fn initialize(
+        &self,
+        endpoint: &Url,
+        headers: &HeadersMap,
+    ) -> Result<(), serde_json::Value> {
+        let init_message = match self.last_initialize.lock().await.clone() {
+            Some(mut init) => {
+                tracing::info!("Re-initializing SSE session after reconnect");
+                init["id"] = serde_json::Value::String(format!("reinit_{}", Uuid::new_v4()));
+                init
+            }
+            None => create_initialize_request(
+                &auto_init_id(),
+                &self.protocol_version,
+                client_capabilities(),
+            ),
+        };
+        let init_response = self.call(endpoint, headers, &init_message).await;
+        if init_response.get("error").is_some() {
+            return Err(init_response);
+        }
+        match self.post(endpoint, headers, &create_initialized_notification()).await {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                tracing::error!("Failed to send initialized notification: {err}");
+                Err(error_payload(-32000, err))
+            }
+        }
+    }
+
+    // Sends a request and waits for its response on the event stream, for
+    // handshakes whose outcome decides what the client does next.
+    async fn call(
+        &self,
+        endpoint: &Url,
+        headers: &HeadersMap,
+        message: &serde_json::Value,
+    ) -> serde_json::Value {
+        let id = message.get("id").cloned().unwrap_or(serde_json::Value::Null);
+        let (tx, rx) = oneshot::channel();
+        let key = match self.pending.register(&id, Waiter::Internal(tx)).await {
+            Ok(key) => key,
+            Err(err) => return wrap_response(message, error_payload(-32600, err)),
+        };
+        match self.post(endpoint, headers, message).await {
+            Ok(None) => {}
+            Ok(Some(payload)) => {
+                dispatch(&self.pending, &self.sender, wrap_response(message, payload)).await;
+            }
+            Err(err) => {
+                self.pending.cancel(&key).await;
+                return wrap_response(message, error_payload(-32000, err));
+            }
+        }
+        match tokio::time::timeout(INITIALIZE_TIMEOUT, rx).await {
+            Ok(Ok(response)) => response,
+            _ => {
+                self.pending.cancel(&key).await;
+                wrap_response(
+                    message,
+                    error_payload(-32000, "Timed out waiting for response on SSE stream"),
+                )
+            }
+        }
+    }
+
+    async fn post(
+        &self,
+        endpoint: &Url,
+        headers: &HeadersMap,
+        message: &serde_json::Value,
+    ) -> Result<Option<serde_json::Value>, String> {
+        let response = send_authorized(self.oauth.as_deref(), headers, |headers| async move {
+            post_once(&self.http, endpoint, &headers, message).await
+        })
+        .await?;
+        read_reply(response).await
+    }
+}
+
+impl MessageSource for SseClient {
+    fn send<'a>(&'a self, message: &'a serde_json::Value) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(SseClient::send(self, message))
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<serde_json::Value> {
+        self.sender.subscribe()
+    }
+
+    fn is_alive(&self) -> BoxFuture<'_, bool> {
+        Box::pin(async move { !self.stream_task.is_finished() })
+    }
+
+    fn restart<'a>(&'a self, runtime: &'a RuntimeArgs) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            *self.headers.write().await = runtime.headers.clone();
+            Ok(())
+        })
+    }
+
+    fn shutdown(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            self.stream_task.abort();
+        })
+    }
+}
+
+async fn stream_events(
+    sse_url: String,
+    headers: Arc<RwLock<HeadersMap>>,
+    link: Arc<watch::Sender<Link>>,
+    pending: Arc<Correlator<Waiter>>,
+    sender: broadcast::Sender<serde_json::Value>,
+    oauth: Option<Arc<OAuthClient>>,
+) {
+    let client = reqwest::Client::new();
+    let mut backoff = Backoff::default();
+    let mut connection = 0;
+    loop {
+        let current_headers = headers.read().await.clone();
+        let open = send_authorized(oauth.as_deref(), &current_headers, |headers| {
+            let (client, sse_url) = (&client, &sse_url);
+            async move { get_once(client, sse_url, &headers).await }
+        });
+        match open.await {
+            Ok(response) if response.status().is_success() => {
+                let stream = response.bytes_stream().eventsource();
+                tokio::pin!(stream);
+                while let Some(event) = stream.next().await {
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(err) => {
+                            tracing::error!("SSE error: {err}");
+                            break;
+                        }
+                    };
+                    if event.event == "endpoint" {
+                        if let Ok(joined) = Url::parse(&sse_url).and_then(|url| url.join(&event.data)) {
+                            connection += 1;
+                            link.send_replace(Link::Open(joined.clone(), connection));
+                            backoff.reset();
+                            tracing::info!("Received message endpoint: {joined}");
+                        }
+                        continue;
+                    }
+                    if event.data.trim().is_empty() {
+                        continue;
+                    }
+                    if let Ok(json) = serde_json::from_str::<serde_json::Value>(&event.data) {
+                        dispatch(&pending, &sender, json).await;
+                    }
+                }
+                tracing::error!("SSE stream closed");
+            }
+            Ok(response) => {
+                tracing::error!("SSE connection failed with status {}", response.status());
+            }
+            Err(err) => tracing::error!("SSE connection failed: {err}"),
+        }
+
+        for (key, waiter) in pending.drain().await {
+            let response = connection_lost(&key.to_value());
+            match waiter {
+                Waiter::Forward => {
+                    let _ = sender.send(response);
+                }
+                Waiter::Internal(tx) => {
+                    let _ = tx.send(response);
+                }
+            }
+        }
+        let delay = backoff.next_delay();
+        link.send_replace(if backoff.gave_up() {
+            Link::Down
+        } else {
+            Link::Connecting
+        });
+        tracing::info!("Reconnecting to SSE in {}ms", delay.as_millis());
+        tokio::time::sleep(delay).await;
+    }
+}
+
+fn connection_lost(id: &serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {
+            "code": -32000,
+            "message": "SSE connection lost before the response arrived",
+        }
+    })
+}
+
+async fn dispatch(
+    pending: &Correlator<Waiter>,
+    sender: &broadcast::Sender<serde_json::Value>,
+    message: serde_json::Value,
+) {
+    match pending.resolve(&message).await {
+        Some((_, Waiter::Internal(tx))) => {
+            let _ = tx.send(message);
+        }
+        Some((_, Waiter::Forward)) => {
+            let _ = sender.send(message);
+        }
+        None if is_response(&message) => {
+            tracing::debug!("Dropping SSE response with unknown id: {message}");
+        }
+        None => {
+            let _ = sender.send(message);
+        }
+    }
+}
+
+async fn get_once(
+    http: &reqwest::Client,
+    sse_url: &str,
+    headers: &HeadersMap,
+) -> Result<reqwest::Response, String> {
+    let mut req = http.get(sse_url);
+    for (k, v) in headers.iter() {
+        req = req.header(k, v);
+    }
+    req.send().await.map_err(|err| err.to_string())
+}
+
+// A single POST with the current headers; `send_authorized` retries it once
+// after a 401.
+async fn post_once(
+    http: &reqwest::Client,
+    endpoint: &Url,
+    headers: &HeadersMap,
+    message: &serde_json::Value,
+) -> Result<reqwest::Response, String> {
+    let mut req = http.post(endpoint.clone()).json(message);
+    for (k, v) in headers.iter() {
+        req = req.header(k, v);
+    }
+    req.send().await.map_err(|err| err.to_string())
+}
+
+// A compliant server sends the response on the event stream, so a POST is
+// not waited on for one. A JSON-RPC result or error in the POST body is still
+// honoured for servers that answer inline.
+async fn read_reply(response: reqwest::Response) -> Result<Option<serde_json::Value>, String> {
+    let status = response.status();
+    let text = response.text().await.map_err(|err| err.to_string())?;
+    let inline = serde_json::from_str::<serde_json::Value>(&text)
+        .ok()
+        .filter(|json| json.get("result").is_some() || json.get("error").is_some());
+    match inline {
+        Some(json) if status.is_success() || json.get("error").is_some() => Ok(Some(json)),
+        _ if status.is_success() => Ok(None),
+        _ => Err(format!("Request failed with status {status}")),
+    }
+}
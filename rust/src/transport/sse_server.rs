@@ -0,0 +1,529 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response, Sse};
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use axum::response::sse::Event;
+use futures::future::BoxFuture;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{mpsc, Mutex};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::support::auth::Principal;
+use crate::support::batch::{BatchCollector, Collected};
+use crate::support::headers::ResponseHeaders;
+use crate::support::jsonrpc::{
+    declares_sampling, empty_batch_error, is_initialize_request, request_ids,
+};
+use crate::support::responder::LocalResponder;
+use crate::support::sampling::SamplingBridge;
+use crate::runtime::{RuntimeApplyResult, RuntimeUpdateRequest};
+use crate::runtime::store::{RuntimeArgsStore, RuntimeArgsUpdate, UpdateResult};
+use crate::transport::mount::{serve_mounts, Mount, RuntimeTarget};
+use crate::transport::shared_child::{Deliver, SharedChild, Upstream};
+use crate::transport::{MessageSink, MessageSource, SourceSpec};
+
+#[derive(Clone)]
+struct AppState {
+    sessions: Arc<Mutex<HashMap<String, mpsc::Sender<Event>>>>,
+    owners: Arc<Mutex<HashMap<String, Principal>>>,
+    batches: Arc<BatchCollector>,
+    responder: Arc<LocalResponder>,
+    sampling_sessions: Arc<Mutex<HashSet<String>>>,
+    shared: Option<Arc<SharedChild>>,
+    children: Arc<Mutex<HashMap<String, Arc<dyn MessageSource>>>>,
+    source: SourceSpec,
+    runtime: RuntimeArgsStore,
+    response_headers: ResponseHeaders,
+    message_path: String,
+    base_url: String,
+}
+
+#[derive(serde::Deserialize)]
+struct MessageQuery {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+}
+
+pub struct SseServer {
+    config: Config,
+}
+
+impl SseServer {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+}
+
+impl MessageSink for SseServer {
+    fn serve(
+        self: Box<Self>,
+        source: SourceSpec,
+        runtime: RuntimeArgsStore,
+        updates: mpsc::Receiver<RuntimeUpdateRequest>,
+    ) -> BoxFuture<'static, Result<(), String>> {
+        Box::pin(run(self.config, source, runtime, updates))
+    }
+}
+
+async fn run(
+    config: Config,
+    source: SourceSpec,
+    runtime: RuntimeArgsStore,
+    updates: mpsc::Receiver<RuntimeUpdateRequest>,
+) -> Result<(), String> {
+    tracing::info!(
+        "  - Headers: {}",
+        serde_json::to_string(&config.headers).unwrap_or_else(|_| "(none)".into())
+    );
+    tracing::info!("  - port: {}", config.port);
+    tracing::info!("  - {source}");
+
+    let shared = if config.sse_child_per_session {
+        None
+    } else {
+        Some(SharedChild::open(&config, &source, &runtime).await?)
+    };
+    let mounted = mount(&config, source, runtime.clone(), shared.clone());
+    serve_mounts(&config, vec![mounted], shared, runtime, updates).await
+}
+
+// Mounts the SSE and message routes. `shared` is the shared child, or None
+// for a child per session.
+pub fn mount(
+    config: &Config,
+    source: SourceSpec,
+    runtime: RuntimeArgsStore,
+    shared: Option<Arc<SharedChild>>,
+) -> Mount {
+    if !config.base_url.is_empty() {
+        tracing::info!("  - baseUrl: {}", config.base_url);
+    }
+    tracing::info!("  - ssePath: {}", config.sse_path);
+    tracing::info!("  - messagePath: {}", config.message_path);
+    if config.sse_child_per_session {
+        tracing::info!("  - sseChildPerSession: true");
+    }
+
+    let children: Arc<Mutex<HashMap<String, Arc<dyn MessageSource>>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+
+    let sessions: Arc<Mutex<HashMap<String, mpsc::Sender<Event>>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+    let responder = Arc::new(LocalResponder::new(&[], SamplingBridge::from_config(config)));
+
+    let remote = source.is_remote();
+    let state = AppState {
+        sessions: sessions.clone(),
+        owners: Arc::new(Mutex::new(HashMap::new())),
+        batches: Arc::new(BatchCollector::default()),
+        responder,
+        sampling_sessions: Arc::new(Mutex::new(HashSet::new())),
+        shared: shared.clone(),
+        children: children.clone(),
+        source,
+        runtime: runtime.clone(),
+        response_headers: ResponseHeaders::new(&config.headers, runtime.clone(), remote),
+        message_path: config.message_path.clone(),
+        base_url: config.base_url.clone(),
+    };
+
+    tokio::spawn(expire_batches(
+        state.batches.clone(),
+        sessions.clone(),
+        Duration::from_millis(config.request_timeout),
+    ));
+
+    let router = Router::new()
+        .route(&config.sse_path, get(sse_handler))
+        .route(&config.message_path, post(message_handler))
+        .with_state(state.clone());
+
+    let runtime_target = Arc::new(SseRuntime {
+        shared: shared.is_some(),
+        remote,
+        sessions,
+        children,
+        runtime,
+    });
+
+    tracing::info!(
+        "SSE endpoint: http://localhost:{}{}",
+        config.port, config.sse_path
+    );
+    tracing::info!(
+        "POST messages: http://localhost:{}{}",
+        config.port, config.message_path
+    );
+
+    Mount {
+        router,
+        health: Arc::new(move || {
+            let state = state.clone();
+            Box::pin(async move { health_handler(state).await.into_response() })
+        }),
+        runtime: runtime_target,
+    }
+}
+
+struct SseRuntime {
+    shared: bool,
+    remote: bool,
+    sessions: Arc<Mutex<HashMap<String, mpsc::Sender<Event>>>>,
+    children: Arc<Mutex<HashMap<String, Arc<dyn MessageSource>>>>,
+    runtime: RuntimeArgsStore,
+}
+
+impl RuntimeTarget for SseRuntime {
+    fn apply_global<'a>(
+        &'a self,
+        update: &'a UpdateResult,
+    ) -> BoxFuture<'a, Option<RuntimeApplyResult>> {
+        Box::pin(async move {
+            if self.shared || !update.restarts(self.remote) {
+                return None;
+            }
+            let sessions: Vec<(String, Arc<dyn MessageSource>)> = self
+                .children
+                .lock()
+                .await
+                .iter()
+                .map(|(id, child)| (id.clone(), child.clone()))
+                .collect();
+            for (id, child) in sessions {
+                let args = self.runtime.get_effective(Some(&id)).await;
+                if let Err(err) = child.restart(&args).await {
+                    tracing::error!("Failed to restart child for session {id}: {err}");
+                }
+            }
+            Some(if update.restart_needed {
+                RuntimeApplyResult::ok("Restarted all sessions with new runtime args", true)
+            } else {
+                RuntimeApplyResult::ok("Updated remote server headers for all sessions", false)
+            })
+        })
+    }
+
+    fn apply_session<'a>(
+        &'a self,
+        session_id: &'a str,
+        update: RuntimeArgsUpdate,
+    ) -> BoxFuture<'a, Option<RuntimeApplyResult>> {
+        Box::pin(async move {
+            if self.shared {
+                return self.sessions.lock().await.contains_key(session_id).then(|| {
+                    RuntimeApplyResult::error(
+                        "Per-session runtime overrides require --sseChildPerSession for SSE output",
+                    )
+                });
+            }
+            let child = self.children.lock().await.get(session_id).cloned()?;
+            let update_result = self.runtime.update_session(session_id, update).await;
+            if !update_result.restarts(self.remote) {
+                return Some(RuntimeApplyResult::ok("Updated session runtime args", false));
+            }
+            let args = self.runtime.get_effective(Some(session_id)).await;
+            Some(match child.restart(&args).await {
+                Err(_) => RuntimeApplyResult::error("Failed to restart session"),
+                Ok(()) if update_result.restart_needed => {
+                    RuntimeApplyResult::ok("Restarted session with new runtime args", true)
+                }
+                Ok(()) => RuntimeApplyResult::ok("Updated remote server headers for session", false),
+            })
+        })
+    }
+}
+
+async fn sse_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    let session_id = Uuid::new_v4().to_string();
+    let (tx, rx) = mpsc::channel(64);
+    {
+        let mut sessions = state.sessions.lock().await;
+        sessions.insert(session_id.clone(), tx.clone());
+    }
+    if let Some(Extension(principal)) = principal {
+        tracing::info!("SSE session {session_id} opened by {principal}");
+        state.owners.lock().await.insert(session_id.clone(), principal);
+    }
+    let guard = SessionChildGuard {
+        state: state.clone(),
+        session_id: session_id.clone(),
+    };
+
+    let endpoint = if state.base_url.is_empty() {
+        format!("{}?sessionId={}", state.message_path, session_id)
+    } else {
+        format!(
+            "{}{}?sessionId={}",
+            state.base_url, state.message_path, session_id
+        )
+    };
+    let _ = tx
+        .send(Event::default().event("endpoint").data(endpoint))
+        .await;
+
+    if let Some(shared) = &state.shared {
+        let deliver = session_deliver(&state, &session_id, tx);
+        shared.register(&session_id, deliver).await;
+    } else if let Err(err) = spawn_session_child(&state, &session_id, tx).await {
+        tracing::error!("Failed to start child for session {session_id}: {err}");
+        let mut response =
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start child").into_response();
+        state.response_headers.apply(None, &mut response).await;
+        return response;
+    }
+
+    let stream = ReceiverStream::new(rx).map(move |event| {
+        let _ = &guard;
+        Ok::<Event, std::convert::Infallible>(event)
+    });
+    let sse = Sse::new(stream).keep_alive(axum::response::sse::KeepAlive::default());
+    let mut response = sse.into_response();
+    state.response_headers.apply(Some(&session_id), &mut response).await;
+    response
+}
+
+struct SessionChildGuard {
+    state: AppState,
+    session_id: String,
+}
+
+impl Drop for SessionChildGuard {
+    fn drop(&mut self) {
+        let state = self.state.clone();
+        let session_id = self.session_id.clone();
+        tokio::spawn(async move {
+            close_session(&state, &session_id).await;
+        });
+    }
+}
+
+async fn spawn_session_child(
+    state: &AppState,
+    session_id: &str,
+    tx: mpsc::Sender<Event>,
+) -> Result<(), String> {
+    state.runtime.register_session(session_id).await;
+    let args = state.runtime.get_effective(Some(session_id)).await;
+    let child = state.source.open(&args, false).await?;
+    let mut rx = child.subscribe();
+    state
+        .children
+        .lock()
+        .await
+        .insert(session_id.to_string(), child.clone());
+
+    let session = session_id.to_string();
+    let batches = state.batches.clone();
+    let responder = state.responder.clone();
+    let sampling_sessions = state.sampling_sessions.clone();
+    tokio::spawn(async move {
+        loop {
+            let msg = match rx.recv().await {
+                Ok(msg) => msg,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::error!("Session {session} lagged, dropped {skipped} child messages");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            if responder.bridges_sampling(&msg)
+                && !sampling_sessions.lock().await.contains(&session)
+            {
+                responder.answer_sampling(&child, msg);
+                continue;
+            }
+            let msg = match batches.collect(&session, msg).await {
+                Collected::Pass(msg) | Collected::Complete(msg) => msg,
+                Collected::Held | Collected::Late => continue,
+            };
+            let Some(event) = to_event(&msg) else {
+                continue;
+            };
+            if tx.send(event).await.is_err() {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn close_session(state: &AppState, session_id: &str) {
+    state.sessions.lock().await.remove(session_id);
+    state.owners.lock().await.remove(session_id);
+    if let Some(shared) = &state.shared {
+        shared.unregister(session_id).await;
+    }
+    state.batches.forget(session_id).await;
+    state.sampling_sessions.lock().await.remove(session_id);
+    let child = state.children.lock().await.remove(session_id);
+    if let Some(child) = child {
+        tracing::info!("SSE session {session_id} closed, shutting down its child");
+        child.shutdown().await;
+        state.runtime.remove_session(session_id).await;
+    }
+}
+
+async fn message_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<MessageQuery>,
+    Json(payload): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    if query.session_id.is_empty() {
+        let mut response =
+            (StatusCode::BAD_REQUEST, "Missing sessionId parameter").into_response();
+        state.response_headers.apply(Some(&query.session_id), &mut response).await;
+        return response;
+    }
+
+    let session_active = {
+        let mut sessions = state.sessions.lock().await;
+        match sessions.get(&query.session_id) {
+            Some(sender) if !sender.is_closed() => true,
+            Some(_) => {
+                sessions.remove(&query.session_id);
+                false
+            }
+            None => false,
+        }
+    };
+    if !session_active {
+        let mut response = (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("No active SSE connection for session {}", query.session_id),
+        )
+            .into_response();
+        state.response_headers.apply(Some(&query.session_id), &mut response).await;
+        return response;
+    }
+
+    if state.owners.lock().await.get(&query.session_id) != principal.as_deref() {
+        let mut response =
+            (StatusCode::FORBIDDEN, "Session belongs to another principal").into_response();
+        state.response_headers.apply(Some(&query.session_id), &mut response).await;
+        return response;
+    }
+
+    let batch = payload.is_array();
+    let messages = match payload {
+        serde_json::Value::Array(items) if !items.is_empty() => items,
+        serde_json::Value::Array(_) => {
+            let mut response =
+                (StatusCode::BAD_REQUEST, Json(empty_batch_error())).into_response();
+            state.response_headers.apply(Some(&query.session_id), &mut response).await;
+            return response;
+        }
+        other => vec![other],
+    };
+    if let Some(init) = messages.iter().find(|message| is_initialize_request(message)) {
+        let mut sampling_sessions = state.sampling_sessions.lock().await;
+        if declares_sampling(init) {
+            sampling_sessions.insert(query.session_id.clone());
+        } else {
+            sampling_sessions.remove(&query.session_id);
+        }
+    }
+
+    let expected = request_ids(&messages);
+    if batch {
+        state.batches.register(&query.session_id, expected.clone()).await;
+    }
+
+    let upstream = match &state.shared {
+        Some(shared) => Upstream::Shared(shared.clone(), query.session_id.clone()),
+        None => {
+            let session_child = state.children.lock().await.get(&query.session_id).cloned();
+            let Some(child) = session_child else {
+                let mut response = (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    format!("No child process for session {}", query.session_id),
+                )
+                    .into_response();
+                state.response_headers.apply(Some(&query.session_id), &mut response).await;
+                return response;
+            };
+            Upstream::Own(child)
+        }
+    };
+
+    for message in messages {
+        if upstream.send(message).await.is_err() {
+            let mut response =
+                (StatusCode::BAD_GATEWAY, "Failed to write to child").into_response();
+            state.response_headers.apply(Some(&query.session_id), &mut response).await;
+            return response;
+        }
+    }
+
+    let mut response = if batch && expected.is_empty() {
+        StatusCode::ACCEPTED.into_response()
+    } else {
+        StatusCode::OK.into_response()
+    };
+    state.response_headers.apply(Some(&query.session_id), &mut response).await;
+    response
+}
+
+// Responses of a batch are held back until the whole batch is answered.
+fn session_deliver(state: &AppState, session_id: &str, tx: mpsc::Sender<Event>) -> Deliver {
+    let batches = state.batches.clone();
+    let session_id = session_id.to_string();
+    Arc::new(move |msg| {
+        let batches = batches.clone();
+        let session_id = session_id.clone();
+        let tx = tx.clone();
+        Box::pin(async move {
+            let msg = match batches.collect(&session_id, msg).await {
+                Collected::Pass(msg) | Collected::Complete(msg) => msg,
+                Collected::Held | Collected::Late => return true,
+            };
+            match to_event(&msg) {
+                Some(event) => tx.send(event).await.is_ok(),
+                None => true,
+            }
+        })
+    })
+}
+
+// A batch left incomplete by a lost response is answered with errors for the
+// missing ids instead of being held forever.
+async fn expire_batches(
+    batches: Arc<BatchCollector>,
+    sessions: Arc<Mutex<HashMap<String, mpsc::Sender<Event>>>>,
+    timeout: Duration,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        for (session_id, batch) in batches.expire(timeout).await {
+            tracing::error!("Session {session_id}: batch timed out waiting for responses");
+            let tx = sessions.lock().await.get(&session_id).cloned();
+            if let (Some(tx), Some(event)) = (tx, to_event(&batch)) {
+                let _ = tx.send(event).await;
+            }
+        }
+    }
+}
+
+fn to_event(msg: &serde_json::Value) -> Option<Event> {
+    let json = serde_json::to_string(msg).ok()?;
+    Some(Event::default().data(json))
+}
+
+async fn health_handler(state: AppState) -> impl IntoResponse {
+    let mut response = (StatusCode::OK, "ok").into_response();
+    state.response_headers.apply(None, &mut response).await;
+    response
+}
+
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
+use tokio_util::codec::{FramedRead, LinesCodec};
+
+use crate::config::Config;
+use crate::support::correlator::{Correlator, RequestId};
+use crate::support::jsonrpc::{
+    create_cancelled_notification, is_initialize_request, is_request, is_response,
+};
+use crate::support::signals::install_signal_handlers;
+use crate::runtime::{RuntimeApplyResult, RuntimeScope, RuntimeUpdateRequest};
+use crate::runtime::store::RuntimeArgsStore;
+use crate::transport::{MessageSink, MessageSource, SourceSpec};
+
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct StdioServer {
+    config: Config,
+}
+
+impl StdioServer {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+}
+
+impl MessageSink for StdioServer {
+    fn serve(
+        self: Box<Self>,
+        source: SourceSpec,
+        runtime: RuntimeArgsStore,
+        updates: mpsc::Receiver<RuntimeUpdateRequest>,
+    ) -> BoxFuture<'static, Result<(), String>> {
+        Box::pin(run(self.config, source, runtime, updates))
+    }
+}
+
+async fn run(
+    config: Config,
+    source: SourceSpec,
+    runtime: RuntimeArgsStore,
+    mut updates: mpsc::Receiver<RuntimeUpdateRequest>,
+) -> Result<(), String> {
+    tracing::info!("  - {source}");
+    tracing::info!(
+        "  - Headers: {}",
+        serde_json::to_string(&config.headers).unwrap_or_else(|_| "(none)".into())
+    );
+    tracing::info!("Connecting to remote server...");
+
+    let initial_args = runtime.get_effective(None).await;
+    let upstream = source.open(&initial_args, false).await?;
+
+    let signal_upstream = upstream.clone();
+    install_signal_handlers(Some(Arc::new(move || {
+        let upstream = signal_upstream.clone();
+        Box::pin(async move { upstream.shutdown().await })
+    })));
+
+    // A request holds a permit until its response is written, which bounds
+    // how many requests are outstanding upstream at once.
+    let permits = Arc::new(Semaphore::new(config.max_in_flight));
+    let all = u32::try_from(config.max_in_flight)
+        .map_err(|_| format!("maxInFlight is too large: {}", config.max_in_flight))?;
+    let in_flight: Arc<Correlator<OwnedSemaphorePermit>> = Arc::new(Correlator::default());
+
+    // The printer owns stdout, so a request is either answered by its response
+    // or failed by the gateway, never both.
+    let request_timeout = Duration::from_millis(config.request_timeout);
+    let mut rx = upstream.subscribe();
+    let (drain_tx, mut drain_rx) = oneshot::channel::<()>();
+    let (failed_tx, mut failed_rx) = mpsc::unbounded_channel::<RequestId>();
+    let printer_in_flight = in_flight.clone();
+    let printer_upstream = upstream.clone();
+    let printer = tokio::spawn(async move {
+        // Requests the client already got an error for, whose responses are
+        // dropped if they turn up after all.
+        let mut abandoned: HashMap<RequestId, Instant> = HashMap::new();
+        let mut expiry = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            tokio::select! {
+                msg = rx.recv() => match msg {
+                    Ok(msg) => {
+                        if is_abandoned(&mut abandoned, &msg) {
+                            tracing::debug!("Dropping late response: {msg}");
+                            continue;
+                        }
+                        write_stdout(&msg);
+                        printer_in_flight.resolve(&msg).await;
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        // Requests whose responses were among the skipped
+                        // messages are failed when they time out.
+                        tracing::error!("stdout writer lagged, dropped {skipped} messages");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = expiry.tick() => {
+                    for (key, _permit) in printer_in_flight.expire(request_timeout).await {
+                        let id = key.to_value();
+                        tracing::error!("Request {id} timed out");
+                        let error = request_failed(&key, "Timed out waiting for upstream response");
+                        write_stdout(&error);
+                        let cancel = create_cancelled_notification(&id, "Request timed out");
+                        let upstream = printer_upstream.clone();
+                        tokio::spawn(async move {
+                            if let Err(err) = upstream.send(&cancel).await {
+                                tracing::error!("Failed to cancel request upstream: {err}");
+                            }
+                        });
+                        abandoned.insert(key, Instant::now());
+                    }
+                    abandoned.retain(|_, since| since.elapsed() < request_timeout);
+                }
+                Some(key) = failed_rx.recv() => {
+                    // The permit is released only after the error is written.
+                    if let Some(_permit) = printer_in_flight.cancel(&key).await {
+                        let error = request_failed(&key, "Failed to send request upstream");
+                        write_stdout(&error);
+                        abandoned.insert(key, Instant::now());
+                    }
+                }
+                _ = &mut drain_rx => {
+                    loop {
+                        match rx.try_recv() {
+                            Ok(msg) if is_abandoned(&mut abandoned, &msg) => {}
+                            Ok(msg) => write_stdout(&msg),
+                            Err(TryRecvError::Lagged(_)) => continue,
+                            Err(_) => break,
+                        }
+                    }
+                    break;
+                }
+            }
+        }
+    });
+
+    let runtime_store = runtime.clone();
+    let runtime_upstream = upstream.clone();
+    tokio::spawn(async move {
+        while let Some(req) = updates.recv().await {
+            let result = match req.update.scope {
+                RuntimeScope::Global => {
+                    let update_result = runtime_store.update_global(req.update.update).await;
+                    let args = runtime_store.get_effective(None).await;
+                    let _ = runtime_upstream.restart(&args).await;
+                    if update_result.restart_needed {
+                        RuntimeApplyResult::ok(
+                            "Updated runtime args; env/CLI changes require restart of remote server",
+                            false,
+                        )
+                    } else {
+                        RuntimeApplyResult::ok("Updated runtime headers", false)
+                    }
+                }
+                RuntimeScope::Session(_) => RuntimeApplyResult::error(
+                    "Per-session runtime overrides are not supported for stdio output",
+                ),
+            };
+            let _ = req.respond_to.send(result);
+        }
+    });
+
+    // Messages are handed upstream in the order they were read: a request
+    // takes its permit before anything after it is read, and each message
+    // waits for the one before it to be underway.
+    let mut sends = JoinSet::new();
+    let mut previous: Option<oneshot::Receiver<()>> = None;
+    let mut lines = FramedRead::new(tokio::io::stdin(), LinesCodec::new());
+    while let Some(line) = lines.next().await {
+        while sends.try_join_next().is_some() {}
+        let line = line.map_err(|err| err.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Ok(message) = serde_json::from_str::<serde_json::Value>(&line) else {
+            tracing::error!("Invalid JSON from stdin: {line}");
+            continue;
+        };
+        // initialize waits for every earlier request and holds back later
+        // ones until its response has been written.
+        let initialize = is_initialize_request(&message);
+        let permit = if !is_request(&message) {
+            None
+        } else {
+            let needed = if initialize { all } else { 1 };
+            let Ok(permit) = permits.clone().acquire_many_owned(needed).await else {
+                break;
+            };
+            Some(permit)
+        };
+        let (underway, next) = oneshot::channel();
+        let earlier = previous.replace(next);
+        let upstream = upstream.clone();
+        let in_flight = in_flight.clone();
+        let failed = failed_tx.clone();
+        sends.spawn(async move {
+            if let Some(earlier) = earlier {
+                let _ = earlier.await;
+            }
+            forward(upstream.as_ref(), &in_flight, &failed, message, permit, underway).await;
+        });
+        if initialize {
+            drop(permits.acquire_many(all).await);
+        }
+    }
+
+    let drained = tokio::time::timeout(DRAIN_TIMEOUT, async {
+        while sends.join_next().await.is_some() {}
+        let _ = permits.acquire_many(all).await;
+    })
+    .await;
+    if drained.is_err() {
+        tracing::error!("Exiting with requests still waiting for a response");
+    }
+    let _ = drain_tx.send(());
+    let _ = printer.await;
+    upstream.shutdown().await;
+    Ok(())
+}
+
+async fn forward(
+    upstream: &dyn MessageSource,
+    in_flight: &Correlator<OwnedSemaphorePermit>,
+    failed: &mpsc::UnboundedSender<RequestId>,
+    message: serde_json::Value,
+    permit: Option<OwnedSemaphorePermit>,
+    underway: oneshot::Sender<()>,
+) {
+    let mut key = None;
+    if let Some(permit) = permit {
+        let id = message.get("id").cloned().unwrap_or(serde_json::Value::Null);
+        match in_flight.register(&id, permit).await {
+            Ok(registered) => key = Some(registered),
+            Err(err) => tracing::error!("Not tracking request: {err}"),
+        }
+    }
+    // The next message may go once this send has made its first step.
+    let send = upstream.send(&message);
+    tokio::pin!(send);
+    let first = futures::poll!(&mut send);
+    drop(underway);
+    let result = match first {
+        Poll::Ready(result) => result,
+        Poll::Pending => send.await,
+    };
+    if let Err(err) = result {
+        tracing::error!("Failed to send message upstream: {err}");
+        // The printer answers the request with an error, so the client is
+        // not left waiting for a response that will never come.
+        if let Some(key) = key {
+            let _ = failed.send(key);
+        }
+    }
+}
+
+fn request_failed(key: &RequestId, message: &str) -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": key.to_value(),
+        "error": { "code": -32001, "message": message },
+    })
+}
+
+fn is_abandoned(abandoned: &mut HashMap<RequestId, Instant>, message: &serde_json::Value) -> bool {
+    is_response(message)
+        && message
+            .get("id")
+            .and_then(RequestId::from_value)
+            .is_some_and(|key| abandoned.remove(&key).is_some())
+}
+
+fn write_stdout(message: &serde_json::Value) {
+    let mut stdout = std::io::stdout().lock();
+    if writeln!(stdout, "{message}").and_then(|_| stdout.flush()).is_err() {
+        tracing::error!("Failed to write to stdout");
+    }
+}
@@ -0,0 +1,418 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use eventsource_stream::Eventsource;
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use reqwest::StatusCode;
+use tokio::sync::{broadcast, watch, Mutex, RwLock};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::support::backoff::Backoff;
+use crate::support::jsonrpc::{
+    auto_init_id, client_capabilities, create_initialize_request, create_initialized_notification,
+    error_payload, is_initialize_request, is_request, is_response, parse_response_payload,
+    wrap_response,
+};
+use crate::support::oauth_client::{send_authorized, OAuthClient};
+use crate::transport::MessageSource;
+use crate::types::{HeadersMap, RuntimeArgs};
+
+const DELETE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct StreamableHttpClient {
+    http: reqwest::Client,
+    url: String,
+    protocol_version: String,
+    headers: Arc<RwLock<HeadersMap>>,
+    oauth: Option<Arc<OAuthClient>>,
+    session_id: watch::Sender<Option<String>>,
+    initialized: Mutex<bool>,
+    last_initialize: Mutex<Option<serde_json::Value>>,
+    sender: broadcast::Sender<serde_json::Value>,
+    stream_task: JoinHandle<()>,
+}
+
+impl StreamableHttpClient {
+    pub fn connect(
+        url: String,
+        protocol_version: String,
+        runtime: &RuntimeArgs,
+        oauth: Option<Arc<OAuthClient>>,
+    ) -> Arc<Self> {
+        let http = reqwest::Client::new();
+        let (sender, _) = broadcast::channel(256);
+        let headers = Arc::new(RwLock::new(runtime.headers.clone()));
+        let (session_id, session_rx) = watch::channel(None);
+        let stream_task = tokio::spawn(stream_events(
+            http.clone(),
+            url.clone(),
+            headers.clone(),
+            session_rx,
+            sender.clone(),
+            oauth.clone(),
+        ));
+        Arc::new(Self {
+            http,
+            url,
+            protocol_version,
+            headers,
+            oauth,
+            session_id,
+            initialized: Mutex::new(false),
+            last_initialize: Mutex::new(None),
+            sender,
+            stream_task,
+        })
+    }
+
+    pub async fn send(&self, message: &serde_json::Value) -> Result<(), String> {
+        let headers = self.headers.read().await.clone();
+        if !is_request(message) {
+            let response = self.post(&headers, message).await?;
+            if response.status().is_success() {
+                return Ok(());
+            }
+            return Err(format!(
+                "Forwarding {} failed with status {}",
+                message
+                    .get("method")
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("response"),
+                response.status()
+            ));
+        }
+
+        if is_initialize_request(message) {
+            *self.last_initialize.lock().await = Some(message.clone());
+        } else {
+            let mut initialized = self.initialized.lock().await;
+            if !*initialized {
+                if let Err(payload) = self.handshake(&headers).await {
+                    let _ = self.sender.send(wrap_response(message, payload));
+                    return Ok(());
+                }
+                *initialized = true;
+            }
+        }
+
+        let payload = match self.post(&headers, message).await {
+            Ok(response) => self.read_payload(response, message).await,
+            Err(err) => error_payload(-32000, err),
+        };
+
+        // The client follows up with its own initialized notification.
+        if is_initialize_request(message) && payload.get("error").is_none() {
+            *self.initialized.lock().await = true;
+        }
+
+        let _ = self.sender.send(wrap_response(message, payload));
+        Ok(())
+    }
+
+    // A 404 for a request carrying a session id means the server expired the
+    // session: start a new one and replay the request once.
+    async fn post(
+        &self,
+        headers: &HeadersMap,
+        message: &serde_json::Value,
+    ) -> Result<reqwest::Response, String> {
+        let sent_session = self.session_id.borrow().clone();
+        let response = self.post_raw(headers, message).await?;
+        if response.status() != StatusCode::NOT_FOUND
+            || sent_session.is_none()
+            || is_initialize_request(message)
+        {
+            return Ok(response);
+        }
+
+        {
+            let mut initialized = self.initialized.lock().await;
+            if *self.session_id.borrow() == sent_session {
+                tracing::warn!("Streamable HTTP session expired, re-initializing");
+                self.session_id.send_replace(None);
+                *initialized = false;
+                self.handshake(headers).await.map_err(|payload| {
+                    format!("Re-initialize failed: {}", payload["error"])
+                })?;
+                *initialized = true;
+            }
+        }
+        self.post_raw(headers, message).await
+    }
+
+    async fn post_raw(
+        &self,
+        headers: &HeadersMap,
+        message: &serde_json::Value,
+    ) -> Result<reqwest::Response, String> {
+        send_authorized(self.oauth.as_deref(), headers, |headers| async move {
+            self.post_once(&headers, message).await
+        })
+        .await
+    }
+
+    async fn post_once(
+        &self,
+        headers: &HeadersMap,
+        message: &serde_json::Value,
+    ) -> Result<reqwest::Response, String> {
+        let mut req = self
+            .http
+            .post(&self.url)
+            .header("Accept", "application/json, text/event-stream")
+            .json(message);
+        for (k, v) in headers.iter() {
+            req = req.header(k, v);
+        }
+        let session = self.session_id.borrow().clone();
+        if let Some(sid) = session {
+            req = req.header("Mcp-Session-Id", sid);
+        }
+        let response = req.send().await.map_err(|err| err.to_string())?;
+        if let Some(sid) = response
+            .headers()
+            .get("Mcp-Session-Id")
+            .and_then(|v| v.to_str().ok())
+        {
+            self.session_id.send_if_modified(|current| {
+                if current.as_deref() == Some(sid) {
+                    return false;
+                }
+                *current = Some(sid.to_string());
+                true
+            });
+        }
+        Ok(response)
+    }
+
+    // Initializes a session on the client's behalf, replaying the client's own
+    // initialize when it sent one.
+    async fn handshake(&self, headers: &HeadersMap) -> Result<(), serde_json::Value> {
+        let init_message = match self.last_initialize.lock().await.clone() {
+            Some(mut init) => {
+                init["id"] = serde_json::Value::String(format!("reinit_{}", Uuid::new_v4()));
+                init
+            }
+            None => create_initialize_request(
+                &auto_init_id(),
+                &self.protocol_version,
+                client_capabilities(),
+            ),
+        };
+        let payload = match self.post_raw(headers, &init_message).await {
+            Ok(response) => self.read_payload(response, &init_message).await,
+            Err(err) => error_payload(-32000, err),
+        };
+        if payload.get("error").is_some() {
+            return Err(payload);
+        }
+        match self
+            .post_raw(headers, &create_initialized_notification())
+            .await
+        {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => tracing::error!(
+                "Initialized notification failed with status {}",
+                response.status()
+            ),
+            Err(err) => tracing::error!("Failed to send initialized notification: {err}"),
+        }
+        Ok(())
+    }
+
+    async fn read_payload(
+        &self,
+        response: reqwest::Response,
+        request: &serde_json::Value,
+    ) -> serde_json::Value {
+        let is_stream = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/event-stream"));
+        if is_stream && response.status().is_success() {
+            return read_response_stream(response, request, &self.sender).await;
+        }
+        match parse_response_payload(response).await {
+            Ok(payload) => payload,
+            Err(err) => error_payload(-32000, err),
+        }
+    }
+
+    async fn delete_session(&self) {
+        let Some(sid) = self.session_id.send_replace(None) else {
+            return;
+        };
+        let mut req = self.http.delete(&self.url).header("Mcp-Session-Id", &sid);
+        for (k, v) in self.headers.read().await.iter() {
+            req = req.header(k, v);
+        }
+        match tokio::time::timeout(DELETE_TIMEOUT, req.send()).await {
+            Ok(Ok(response)) => {
+                tracing::info!("Closed Streamable HTTP session {sid} ({})", response.status());
+            }
+            Ok(Err(err)) => tracing::error!("Failed to close Streamable HTTP session {sid}: {err}"),
+            Err(_) => tracing::error!("Timed out closing Streamable HTTP session {sid}"),
+        }
+    }
+}
+
+impl MessageSource for StreamableHttpClient {
+    fn send<'a>(&'a self, message: &'a serde_json::Value) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(StreamableHttpClient::send(self, message))
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<serde_json::Value> {
+        self.sender.subscribe()
+    }
+
+    fn is_alive(&self) -> BoxFuture<'_, bool> {
+        Box::pin(async move { !self.stream_task.is_finished() })
+    }
+
+    fn restart<'a>(&'a self, runtime: &'a RuntimeArgs) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            *self.headers.write().await = runtime.headers.clone();
+            Ok(())
+        })
+    }
+
+    fn shutdown(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            self.stream_task.abort();
+            self.delete_session().await;
+        })
+    }
+}
+
+// Keeps a GET stream open for the current session, reopening it whenever the
+// session changes.
+async fn stream_events(
+    http: reqwest::Client,
+    url: String,
+    headers: Arc<RwLock<HeadersMap>>,
+    mut session_id: watch::Receiver<Option<String>>,
+    sender: broadcast::Sender<serde_json::Value>,
+    oauth: Option<Arc<OAuthClient>>,
+) {
+    let mut backoff = Backoff::default();
+    loop {
+        let sid = match session_id.wait_for(|sid| sid.is_some()).await {
+            Ok(sid) => sid.clone().unwrap_or_default(),
+            Err(_) => return,
+        };
+        session_id.mark_unchanged();
+
+        let current_headers = headers.read().await.clone();
+        let open = send_authorized(oauth.as_deref(), &current_headers, |headers| {
+            let (http, url, sid) = (&http, &url, &sid);
+            async move { get_once(http, url, &headers, sid).await }
+        });
+        let response = tokio::select! {
+            response = open => response,
+            _ = session_id.changed() => continue,
+        };
+        let retry = match response {
+            Ok(response) if response.status().is_success() => {
+                backoff.reset();
+                let stream = response.bytes_stream().eventsource();
+                tokio::pin!(stream);
+                loop {
+                    let event = tokio::select! {
+                        event = stream.next() => event,
+                        _ = session_id.changed() => break false,
+                    };
+                    match event {
+                        Some(Ok(event)) => {
+                            if event.data.trim().is_empty() {
+                                continue;
+                            }
+                            if let Ok(json) =
+                                serde_json::from_str::<serde_json::Value>(&event.data)
+                            {
+                                let _ = sender.send(json);
+                            }
+                        }
+                        Some(Err(err)) => {
+                            tracing::error!("Streamable HTTP SSE error: {err}");
+                            break true;
+                        }
+                        None => break true,
+                    }
+                }
+            }
+            Ok(response) if response.status() == StatusCode::METHOD_NOT_ALLOWED => {
+                tracing::debug!("Server does not offer a GET stream for session {sid}");
+                if session_id.changed().await.is_err() {
+                    return;
+                }
+                false
+            }
+            Ok(response) => {
+                tracing::error!(
+                    "Streamable HTTP SSE connection failed with status {}",
+                    response.status()
+                );
+                true
+            }
+            Err(err) => {
+                tracing::error!("Streamable HTTP SSE connection failed: {err}");
+                true
+            }
+        };
+        if retry {
+            tokio::select! {
+                _ = tokio::time::sleep(backoff.next_delay()) => {}
+                _ = session_id.changed() => {}
+            }
+        }
+    }
+}
+
+async fn get_once(
+    http: &reqwest::Client,
+    url: &str,
+    headers: &HeadersMap,
+    sid: &str,
+) -> Result<reqwest::Response, String> {
+    let mut req = http.get(url).header("Accept", "text/event-stream");
+    for (k, v) in headers.iter() {
+        req = req.header(k, v);
+    }
+    req.header("Mcp-Session-Id", sid)
+        .send()
+        .await
+        .map_err(|err| err.to_string())
+}
+
+// Forwards whatever the server sends on a streamed POST response as it
+// arrives, until the response to the posted request itself.
+async fn read_response_stream(
+    resp: reqwest::Response,
+    request: &serde_json::Value,
+    sender: &broadcast::Sender<serde_json::Value>,
+) -> serde_json::Value {
+    let id = request.get("id");
+    let stream = resp.bytes_stream().eventsource();
+    tokio::pin!(stream);
+    while let Some(event) = stream.next().await {
+        let event = match event {
+            Ok(event) => event,
+            Err(err) => return error_payload(-32000, err.to_string()),
+        };
+        if event.data.trim().is_empty() {
+            continue;
+        }
+        let Ok(json) = serde_json::from_str::<serde_json::Value>(&event.data) else {
+            tracing::error!("Invalid JSON on Streamable HTTP response stream: {}", event.data);
+            continue;
+        };
+        if is_response(&json) && json.get("id") == id {
+            return json;
+        }
+        let _ = sender.send(json);
+    }
+    error_payload(-32000, "Response stream ended before the response arrived")
+}
@@ -0,0 +1,1356 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response, Sse};
+use axum::routing::{delete, get, post};
+use axum::{Extension, Json, Router};
+use axum::response::sse::Event;
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, Mutex, oneshot, mpsc};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::support::auth::Principal;
+use crate::support::correlator::{Correlator, RequestId};
+use crate::support::event_store::{EventStore, Replay};
+use crate::support::headers::ResponseHeaders;
+use crate::support::jsonrpc::{
+    auto_init_id, create_initialize_request, create_initialized_notification, declares_sampling,
+    empty_batch_error, is_initialize_request, is_request, is_response, request_ids,
+};
+use crate::support::session_access_counter::SessionAccessCounter;
+use crate::support::responder::LocalResponder;
+use crate::support::sampling::SamplingBridge;
+use crate::runtime::{RuntimeApplyResult, RuntimeUpdateRequest};
+use crate::runtime::store::{RuntimeArgsStore, RuntimeArgsUpdate, UpdateResult};
+use crate::transport::child_pool::ChildPool;
+use crate::transport::mount::{serve_mounts, Mount, RuntimeTarget};
+use crate::transport::shared_child::SharedChild;
+use crate::transport::{MessageSink, MessageSource, SourceSpec};
+use crate::types::RuntimeArgs;
+
+#[derive(Clone)]
+struct AppState {
+    runtime: RuntimeArgsStore,
+    response_headers: ResponseHeaders,
+    manager: Arc<SessionManager>,
+    protocol_version: String,
+    source: SourceSpec,
+    pool: Option<Arc<ChildPool>>,
+    shared: Option<Arc<SharedChild>>,
+    responder: Arc<LocalResponder>,
+}
+
+#[derive(Clone)]
+struct SessionResponseGuard {
+    inner: Arc<SessionResponseGuardInner>,
+}
+
+struct SessionResponseGuardInner {
+    manager: Arc<SessionManager>,
+    session_id: String,
+    reason: String,
+    fired: AtomicBool,
+}
+
+impl Drop for SessionResponseGuard {
+    fn drop(&mut self) {
+        if self.inner.fired.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let manager = self.inner.manager.clone();
+        let session_id = self.inner.session_id.clone();
+        let reason = self.inner.reason.clone();
+        tokio::spawn(async move {
+            manager.session_dec(&session_id, &reason).await;
+        });
+    }
+}
+
+fn attach_session_guard(
+    response: &mut Response,
+    manager: Arc<SessionManager>,
+    session_id: String,
+    reason: &str,
+) {
+    response.extensions_mut().insert(SessionResponseGuard {
+        inner: Arc::new(SessionResponseGuardInner {
+            manager,
+            session_id,
+            reason: reason.to_string(),
+            fired: AtomicBool::new(false),
+        }),
+    });
+}
+
+pub struct StreamableHttpServer {
+    config: Config,
+}
+
+impl StreamableHttpServer {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+}
+
+impl MessageSink for StreamableHttpServer {
+    fn serve(
+        self: Box<Self>,
+        source: SourceSpec,
+        runtime: RuntimeArgsStore,
+        updates: mpsc::Receiver<RuntimeUpdateRequest>,
+    ) -> BoxFuture<'static, Result<(), String>> {
+        Box::pin(run(self.config, source, runtime, updates))
+    }
+}
+
+async fn run(
+    config: Config,
+    source: SourceSpec,
+    runtime: RuntimeArgsStore,
+    updates: mpsc::Receiver<RuntimeUpdateRequest>,
+) -> Result<(), String> {
+    tracing::info!(
+        "  - Headers: {}",
+        serde_json::to_string(&config.headers).unwrap_or_else(|_| "(none)".into())
+    );
+    tracing::info!("  - port: {}", config.port);
+    tracing::info!("  - {source}");
+
+    let mounted = mount(&config, source, runtime.clone(), None);
+    serve_mounts(&config, vec![mounted], None, runtime, updates).await
+}
+
+// Mounts the Streamable HTTP route. With `shared`, sessions and stateless
+// requests are clients of the shared child instead of running their own.
+pub fn mount(
+    config: &Config,
+    source: SourceSpec,
+    runtime: RuntimeArgsStore,
+    shared: Option<Arc<SharedChild>>,
+) -> Mount {
+    tracing::info!("  - streamableHttpPath: {}", config.streamable_http_path);
+    if config.stateful {
+        tracing::info!(
+            "  - Session timeout: {}",
+            config
+                .session_timeout
+                .map(|v| format!("{v}ms"))
+                .unwrap_or_else(|| "disabled".to_string())
+        );
+    } else if config.stateless_pool_size > 0 && shared.is_none() {
+        tracing::info!("  - statelessPoolSize: {}", config.stateless_pool_size);
+        tracing::info!(
+            "  - statelessPoolMaxUses: {}",
+            config
+                .stateless_pool_max_uses
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| "unlimited".to_string())
+        );
+    }
+
+    let responder = Arc::new(LocalResponder::new(
+        &config.roots,
+        SamplingBridge::from_config(config),
+    ));
+
+    let manager = Arc::new(SessionManager::new(
+        source.clone(),
+        runtime.clone(),
+        config.session_timeout,
+        Duration::from_millis(config.request_timeout),
+        Duration::from_millis(config.client_response_timeout),
+        responder.clone(),
+        shared.clone(),
+    ));
+
+    let pool = if !config.stateful && config.stateless_pool_size > 0 && shared.is_none() {
+        let pool = ChildPool::new(
+            source.clone(),
+            runtime.clone(),
+            config.protocol_version.clone(),
+            responder.capabilities(),
+            config.stateless_pool_size,
+            config.stateless_pool_max_uses,
+        );
+        pool.fill();
+        Some(pool)
+    } else {
+        None
+    };
+
+    let remote = source.is_remote();
+    let state = AppState {
+        runtime: runtime.clone(),
+        response_headers: ResponseHeaders::new(&config.headers, runtime.clone(), remote),
+        manager: manager.clone(),
+        protocol_version: config.protocol_version.clone(),
+        source,
+        pool: pool.clone(),
+        shared: shared.clone(),
+        responder,
+    };
+
+    let runtime_target = Arc::new(StreamableHttpRuntime {
+        stateful: config.stateful,
+        shared: shared.is_some(),
+        remote,
+        pool,
+        manager,
+        runtime,
+    });
+
+    let mut router = Router::new();
+
+    if config.stateful {
+        router = router
+            .route(&config.streamable_http_path, post(stateful_post))
+            .route(&config.streamable_http_path, get(stateful_get))
+            .route(&config.streamable_http_path, delete(stateful_delete));
+    } else {
+        router = router
+            .route(&config.streamable_http_path, post(stateless_post))
+            .route(&config.streamable_http_path, get(stateless_method_not_allowed))
+            .route(&config.streamable_http_path, delete(stateless_method_not_allowed));
+    }
+
+    tracing::info!(
+        "StreamableHttp endpoint: http://localhost:{}{}",
+        config.port, config.streamable_http_path
+    );
+
+    Mount {
+        router: router.with_state(state.clone()),
+        health: Arc::new(move || {
+            let state = state.clone();
+            Box::pin(async move { health_handler(state).await.into_response() })
+        }),
+        runtime: runtime_target,
+    }
+}
+
+struct StreamableHttpRuntime {
+    stateful: bool,
+    shared: bool,
+    remote: bool,
+    pool: Option<Arc<ChildPool>>,
+    manager: Arc<SessionManager>,
+    runtime: RuntimeArgsStore,
+}
+
+impl RuntimeTarget for StreamableHttpRuntime {
+    fn apply_global<'a>(
+        &'a self,
+        update: &'a UpdateResult,
+    ) -> BoxFuture<'a, Option<RuntimeApplyResult>> {
+        Box::pin(async move {
+            if self.shared || !update.restarts(self.remote) {
+                None
+            } else if let Some(pool) = &self.pool {
+                pool.rebuild().await;
+                Some(if update.restart_needed {
+                    RuntimeApplyResult::ok("Rebuilt stateless child pool with new args", true)
+                } else {
+                    RuntimeApplyResult::ok("Rebuilt stateless child pool with new headers", false)
+                })
+            } else if self.stateful {
+                self.manager.restart_all().await;
+                Some(if update.restart_needed {
+                    RuntimeApplyResult::ok("Restarted all sessions with new runtime args", true)
+                } else {
+                    RuntimeApplyResult::ok("Updated remote server headers for all sessions", false)
+                })
+            } else {
+                Some(RuntimeApplyResult::ok(
+                    "Updated runtime args for future requests",
+                    false,
+                ))
+            }
+        })
+    }
+
+    fn apply_session<'a>(
+        &'a self,
+        session_id: &'a str,
+        update: RuntimeArgsUpdate,
+    ) -> BoxFuture<'a, Option<RuntimeApplyResult>> {
+        Box::pin(async move {
+            if !self.stateful {
+                return None;
+            }
+            self.manager.get_session(session_id).await?;
+            if self.shared {
+                return Some(RuntimeApplyResult::error(
+                    "Per-session runtime overrides are not supported while Streamable HTTP \
+                     shares a child with other outputs",
+                ));
+            }
+            let update_result = self.runtime.update_session(session_id, update).await;
+            if !update_result.restarts(self.remote) {
+                return Some(RuntimeApplyResult::ok("Updated session runtime args", false));
+            }
+            Some(match self.manager.restart_session(session_id).await {
+                Err(_) => RuntimeApplyResult::error("Failed to restart session"),
+                Ok(()) if update_result.restart_needed => {
+                    RuntimeApplyResult::ok("Restarted session with new runtime args", true)
+                }
+                Ok(()) => RuntimeApplyResult::ok("Updated remote server headers for session", false),
+            })
+        })
+    }
+}
+
+async fn health_handler(state: AppState) -> impl IntoResponse {
+    let mut response = (StatusCode::OK, "ok").into_response();
+    state.response_headers.apply(None, &mut response).await;
+    response
+}
+
+async fn stateless_post(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    if is_empty_batch(&payload) {
+        return empty_batch_response();
+    }
+    let runtime = state.runtime.get_effective(None).await;
+    if accepts_event_stream(&headers) && is_request(&payload) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let task_state = state.clone();
+        tokio::spawn(async move {
+            let id = payload.get("id").cloned().unwrap_or(serde_json::Value::Null);
+            let exchange = handle_stateless_request(&task_state, runtime, payload, Some(&tx));
+            // A client that goes away cancels the exchange, which frees its child.
+            let result = tokio::select! {
+                result = exchange => result,
+                _ = tx.closed() => return,
+            };
+            let message = match result {
+                Ok(Some(resp)) => resp,
+                Ok(None) => return,
+                Err(err) => serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "error": { "code": -32603, "message": err },
+                    "id": id
+                }),
+            };
+            let _ = tx.send(message);
+        });
+        let mut response = event_stream_response(rx);
+        state.response_headers.apply(None, &mut response).await;
+        return response;
+    }
+    match handle_stateless_request(&state, runtime, payload, None).await {
+        Ok(Some(resp)) => {
+            let mut response = Json(resp).into_response();
+            state.response_headers.apply(None, &mut response).await;
+            response
+        }
+        Ok(None) => {
+            let mut response = StatusCode::ACCEPTED.into_response();
+            state.response_headers.apply(None, &mut response).await;
+            response
+        }
+        Err(err) => {
+            let mut response = (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "error": { "code": -32603, "message": err },
+                    "id": serde_json::Value::Null
+                })),
+            )
+                .into_response();
+            state.response_headers.apply(None, &mut response).await;
+            response
+        }
+    }
+}
+
+async fn stateless_method_not_allowed() -> impl IntoResponse {
+    (
+        StatusCode::METHOD_NOT_ALLOWED,
+        Json(serde_json::json!({
+            "jsonrpc": "2.0",
+            "error": { "code": -32000, "message": "Method not allowed." },
+            "id": serde_json::Value::Null
+        })),
+    )
+}
+
+async fn stateful_post(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    headers: HeaderMap,
+    Json(payload): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    let session_header = headers
+        .get("Mcp-Session-Id")
+        .or_else(|| headers.get("mcp-session-id"))
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+
+    if is_empty_batch(&payload) {
+        return empty_batch_response();
+    }
+
+    let mut should_inc = false;
+    let (session_id, session) = if let Some(id) = session_header {
+        if let Some(session) = state.manager.get_session(&id).await {
+            if !session.owned_by(principal.as_deref()) {
+                return forbidden_session();
+            }
+            should_inc = true;
+            (id, session)
+        } else {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "error": { "code": -32001, "message": "Session not found" },
+                    "id": serde_json::Value::Null
+                })),
+            )
+                .into_response();
+        }
+    } else if is_initialize_request(&payload) || batch_has_initialize(&payload) {
+        let owner = principal.map(|Extension(principal)| principal);
+        let session = match state.manager.create_session(owner).await {
+            Ok(session) => session,
+            Err(err) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "error": { "code": -32603, "message": err },
+                        "id": serde_json::Value::Null
+                    })),
+                )
+                    .into_response();
+            }
+        };
+        let session_id = session.id.clone();
+        (session_id, session)
+    } else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "jsonrpc": "2.0",
+                "error": { "code": -32000, "message": "Bad Request: No valid session ID provided" },
+                "id": serde_json::Value::Null
+            })),
+        )
+            .into_response();
+    };
+
+    if should_inc {
+        state.manager
+            .session_inc(&session_id, "POST request for existing session")
+            .await;
+    }
+
+    let response = if let serde_json::Value::Array(messages) = payload {
+        match session.request_batch(messages).await {
+            Ok(responses) if responses.is_empty() => StatusCode::ACCEPTED.into_response(),
+            Ok(responses) => Json(serde_json::Value::Array(responses)).into_response(),
+            Err(err) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "error": { "code": -32603, "message": err },
+                    "id": serde_json::Value::Null
+                })),
+            )
+                .into_response(),
+        }
+    } else if accepts_event_stream(&headers) && is_request(&payload) {
+        let id = payload.get("id").cloned().unwrap_or(serde_json::Value::Null);
+        match session.request_stream(payload).await {
+            Ok(rx) => event_stream_response(rx),
+            Err(err) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "error": { "code": -32603, "message": err },
+                    "id": id
+                })),
+            )
+                .into_response(),
+        }
+    } else if is_request(&payload) {
+        let id = payload.get("id").cloned().unwrap_or(serde_json::Value::Null);
+        match session.request(payload).await {
+            Ok(resp) => Json(resp).into_response(),
+            Err(err) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "error": { "code": -32603, "message": err },
+                    "id": id
+                })),
+            )
+                .into_response(),
+        }
+    } else {
+        if session.send(&payload).await.is_err() {
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "error": { "code": -32603, "message": "Failed to send message" },
+                    "id": serde_json::Value::Null
+                })),
+            )
+                .into_response()
+        } else {
+            StatusCode::ACCEPTED.into_response()
+        }
+    };
+
+    let mut response = response;
+    response
+        .headers_mut()
+        .insert("Mcp-Session-Id", HeaderValue::from_str(&session_id).unwrap());
+    state.response_headers.apply(Some(&session_id), &mut response).await;
+    attach_session_guard(
+        &mut response,
+        state.manager.clone(),
+        session_id,
+        "POST response closed",
+    );
+    response
+}
+
+async fn stateful_get(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let session_id = headers
+        .get("Mcp-Session-Id")
+        .or_else(|| headers.get("mcp-session-id"))
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+
+    let Some(session_id) = session_id else {
+        return (StatusCode::BAD_REQUEST, "Invalid or missing session ID").into_response();
+    };
+    let Some(session) = state.manager.get_session(&session_id).await else {
+        return (StatusCode::NOT_FOUND, "Session not found").into_response();
+    };
+    if !session.owned_by(principal.as_deref()) {
+        return forbidden_session();
+    }
+
+    state
+        .manager
+        .session_inc(&session_id, "GET request for existing session")
+        .await;
+
+    let last_event_id = headers
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    let stream = session_event_stream(session.subscribe_events(last_event_id).await);
+    let sse = Sse::new(stream).keep_alive(axum::response::sse::KeepAlive::default());
+    let mut response = sse.into_response();
+    response
+        .headers_mut()
+        .insert("Mcp-Session-Id", HeaderValue::from_str(&session_id).unwrap());
+    state.response_headers.apply(Some(&session_id), &mut response).await;
+    attach_session_guard(
+        &mut response,
+        state.manager.clone(),
+        session_id,
+        "GET response closed",
+    );
+    response
+}
+
+enum StreamItem {
+    Message(u64, serde_json::Value),
+    Missed(u64),
+}
+
+struct EventCursor {
+    session: Arc<Session>,
+    rx: broadcast::Receiver<(u64, serde_json::Value)>,
+    queue: VecDeque<StreamItem>,
+    last_id: u64,
+}
+
+impl EventCursor {
+    fn queue_replay(&mut self, replay: Replay) {
+        if replay.missed > 0 {
+            self.queue.push_back(StreamItem::Missed(replay.missed));
+        }
+        for (id, msg) in replay.events {
+            self.queue.push_back(StreamItem::Message(id, msg));
+        }
+    }
+}
+
+fn session_event_stream(
+    cursor: EventCursor,
+) -> impl futures::Stream<Item = Result<Event, std::convert::Infallible>> {
+    futures::stream::unfold(cursor, |mut cursor| async move {
+        loop {
+            if let Some(item) = cursor.queue.pop_front() {
+                let event = match item {
+                    StreamItem::Message(id, msg) => {
+                        cursor.last_id = id;
+                        let Ok(data) = serde_json::to_string(&msg) else {
+                            continue;
+                        };
+                        Event::default().id(id.to_string()).data(data)
+                    }
+                    StreamItem::Missed(count) => {
+                        tracing::warn!(
+                            "Session {} GET stream missed {count} events",
+                            cursor.session.id
+                        );
+                        // A named event rather than a comment, which
+                        // EventSource clients would discard unseen.
+                        let data = serde_json::json!({ "missed": count }).to_string();
+                        Event::default().event("missed").data(data)
+                    }
+                };
+                return Some((Ok(event), cursor));
+            }
+            match cursor.rx.recv().await {
+                Ok((id, _)) if id <= cursor.last_id => continue,
+                Ok((id, msg)) => cursor.queue.push_back(StreamItem::Message(id, msg)),
+                Err(RecvError::Lagged(_)) => {
+                    let replay = cursor.session.events.lock().await.since(cursor.last_id);
+                    cursor.queue_replay(replay);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+async fn stateful_delete(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let session_id = headers
+        .get("Mcp-Session-Id")
+        .or_else(|| headers.get("mcp-session-id"))
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+
+    let Some(session_id) = session_id else {
+        return (StatusCode::BAD_REQUEST, "Invalid or missing session ID").into_response();
+    };
+
+    let Some(session) = state.manager.get_session(&session_id).await else {
+        return (StatusCode::NOT_FOUND, "Session not found").into_response();
+    };
+    if !session.owned_by(principal.as_deref()) {
+        return forbidden_session();
+    }
+
+    state
+        .manager
+        .session_inc(&session_id, "DELETE request for existing session")
+        .await;
+
+    if state.manager.remove_session(&session_id).await {
+        let mut response = StatusCode::OK.into_response();
+        state.response_headers.apply(Some(&session_id), &mut response).await;
+        attach_session_guard(
+            &mut response,
+            state.manager.clone(),
+            session_id,
+            "DELETE response closed",
+        );
+        response
+    } else {
+        (StatusCode::NOT_FOUND, "Session not found").into_response()
+    }
+}
+
+fn forbidden_session() -> Response {
+    (StatusCode::FORBIDDEN, "Session belongs to another principal").into_response()
+}
+
+fn is_empty_batch(payload: &serde_json::Value) -> bool {
+    payload.as_array().is_some_and(|items| items.is_empty())
+}
+
+fn batch_has_initialize(payload: &serde_json::Value) -> bool {
+    payload
+        .as_array()
+        .is_some_and(|items| items.iter().any(is_initialize_request))
+}
+
+fn empty_batch_response() -> Response {
+    (StatusCode::BAD_REQUEST, Json(empty_batch_error())).into_response()
+}
+
+fn accepts_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(axum::http::header::ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .any(|v| v.contains("text/event-stream"))
+}
+
+fn event_stream_response(rx: mpsc::UnboundedReceiver<serde_json::Value>) -> Response {
+    let stream = UnboundedReceiverStream::new(rx).filter_map(|value| async move {
+        let data = serde_json::to_string(&value).ok()?;
+        Some(Ok::<Event, std::convert::Infallible>(Event::default().data(data)))
+    });
+    Sse::new(stream)
+        .keep_alive(axum::response::sse::KeepAlive::default())
+        .into_response()
+}
+
+
+enum Responder {
+    Json(oneshot::Sender<serde_json::Value>),
+    Stream(mpsc::UnboundedSender<serde_json::Value>),
+}
+
+impl Responder {
+    fn is_closed(&self) -> bool {
+        match self {
+            Responder::Json(tx) => tx.is_closed(),
+            Responder::Stream(tx) => tx.is_closed(),
+        }
+    }
+}
+
+struct RequestStream {
+    key: RequestId,
+    progress_token: Option<serde_json::Value>,
+    tx: mpsc::UnboundedSender<serde_json::Value>,
+}
+
+const EVENT_STORE_CAPACITY: usize = 256;
+
+struct Session {
+    id: String,
+    principal: Option<Principal>,
+    child: Arc<dyn MessageSource>,
+    pending: Correlator<Responder>,
+    // Requests already answered with a timeout error, whose late responses
+    // are dropped.
+    abandoned: Correlator<()>,
+    request_timeout: Duration,
+    client_requests: Correlator<String>,
+    streams: Mutex<Vec<RequestStream>>,
+    responder: Arc<LocalResponder>,
+    client_sampling: AtomicBool,
+    closed: AtomicBool,
+    events: Mutex<EventStore>,
+    notifications: broadcast::Sender<(u64, serde_json::Value)>,
+}
+
+impl Session {
+    async fn new(
+        id: String,
+        principal: Option<Principal>,
+        source: &SourceSpec,
+        runtime: RuntimeArgs,
+        responder: Arc<LocalResponder>,
+        request_timeout: Duration,
+        shared: Option<&Arc<SharedChild>>,
+    ) -> Result<Self, String> {
+        let child = match shared {
+            Some(shared) => shared.attach(&id).await,
+            None => source.open(&runtime, false).await?,
+        };
+        let (tx, _) = broadcast::channel(64);
+        Ok(Session {
+            id,
+            principal,
+            child,
+            pending: Correlator::default(),
+            abandoned: Correlator::default(),
+            request_timeout,
+            client_requests: Correlator::default(),
+            streams: Mutex::new(Vec::new()),
+            responder,
+            client_sampling: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            events: Mutex::new(EventStore::new(EVENT_STORE_CAPACITY)),
+            notifications: tx,
+        })
+    }
+
+    fn owned_by(&self, principal: Option<&Principal>) -> bool {
+        self.principal.as_ref() == principal
+    }
+
+    async fn start_routing(self: Arc<Self>) {
+        let mut rx = self.child.subscribe();
+        let this = self.clone();
+        tokio::spawn(async move {
+            // Requests are expired here rather than in a task of their own so
+            // a response cannot slip in between the timeout error and the id
+            // being marked abandoned.
+            let mut expiry = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                let msg = tokio::select! {
+                    msg = rx.recv() => match msg {
+                        Ok(msg) => msg,
+                        Err(RecvError::Lagged(skipped)) => {
+                            // Requests whose responses were skipped are failed
+                            // when they time out.
+                            tracing::error!(
+                                "Session {} lagged, dropped {skipped} child messages",
+                                this.id
+                            );
+                            continue;
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = expiry.tick() => {
+                        this.expire_pending().await;
+                        continue;
+                    }
+                };
+                match this.pending.resolve(&msg).await {
+                    Some((_, Responder::Json(sender))) => {
+                        let _ = sender.send(msg);
+                        continue;
+                    }
+                    Some((key, Responder::Stream(tx))) => {
+                        this.streams.lock().await.retain(|stream| stream.key != key);
+                        let _ = tx.send(msg);
+                        continue;
+                    }
+                    None => {}
+                }
+                if this.abandoned.resolve(&msg).await.is_some() {
+                    tracing::debug!("Session {}: dropping late response: {msg}", this.id);
+                    continue;
+                }
+                if !this.client_sampling.load(Ordering::SeqCst)
+                    && this.responder.bridges_sampling(&msg)
+                {
+                    this.responder.answer_sampling(&this.child, msg);
+                    continue;
+                }
+                if is_request(&msg) {
+                    let id = msg.get("id").cloned().unwrap_or(serde_json::Value::Null);
+                    let method = msg
+                        .get("method")
+                        .and_then(|m| m.as_str())
+                        .unwrap_or_default()
+                        .to_string();
+                    if let Err(err) = this.client_requests.register(&id, method).await {
+                        tracing::error!("Session {}: {err}", this.id);
+                    }
+                }
+                if this.send_to_related_stream(&msg).await {
+                    continue;
+                }
+                this.publish(msg).await;
+            }
+        });
+    }
+
+    async fn send(&self, message: &serde_json::Value) -> Result<(), String> {
+        // A response to a request that is no longer pending was already
+        // answered with a timeout error, so the child must not see it twice.
+        if is_response(message) && self.client_requests.resolve(message).await.is_none() {
+            tracing::debug!("Session {}: dropping response with unknown id: {message}", self.id);
+            return Ok(());
+        }
+        self.child.send(message).await
+    }
+
+    // Answers server-initiated requests the client never responded to, so the
+    // child is not left waiting forever.
+    fn expire_client_requests(self: Arc<Self>, timeout: Duration) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            while !self.closed.load(Ordering::SeqCst) {
+                interval.tick().await;
+                for (key, method) in self.client_requests.expire(timeout).await {
+                    let id = key.to_value();
+                    tracing::error!(
+                        "Session {}: client did not answer {method} request {id}",
+                        self.id
+                    );
+                    let error = serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {
+                            "code": -32001,
+                            "message": format!("Client did not respond to {method} request"),
+                        }
+                    });
+                    if let Err(err) = self.child.send(&error).await {
+                        tracing::error!("Session {}: failed to send timeout error: {err}", self.id);
+                    }
+                }
+            }
+        });
+    }
+
+    async fn publish(&self, msg: serde_json::Value) {
+        let mut events = self.events.lock().await;
+        let id = events.push(msg.clone());
+        let _ = self.notifications.send((id, msg));
+    }
+
+    async fn subscribe_events(self: &Arc<Self>, last_event_id: Option<u64>) -> EventCursor {
+        let events = self.events.lock().await;
+        let mut cursor = EventCursor {
+            session: self.clone(),
+            rx: self.notifications.subscribe(),
+            queue: VecDeque::new(),
+            last_id: events.last_id(),
+        };
+        if let Some(last_event_id) = last_event_id {
+            cursor.last_id = last_event_id.min(events.last_id());
+            cursor.queue_replay(events.since(last_event_id));
+        }
+        cursor
+    }
+
+    async fn register(
+        &self,
+        message: &serde_json::Value,
+        responder: Responder,
+    ) -> Result<RequestId, String> {
+        if is_initialize_request(message) {
+            self.client_sampling
+                .store(declares_sampling(message), Ordering::SeqCst);
+        }
+        // Callers that gave up waiting leave closed responders behind.
+        self.pending.retain(|responder| !responder.is_closed()).await;
+        let id = message.get("id").cloned().unwrap_or(serde_json::Value::Null);
+        self.pending.register(&id, responder).await
+    }
+
+    async fn request(&self, message: serde_json::Value) -> Result<serde_json::Value, String> {
+        let (tx, rx) = oneshot::channel();
+        let key = self.register(&message, Responder::Json(tx)).await?;
+        if let Err(err) = self.child.send(&message).await {
+            self.pending.cancel(&key).await;
+            return Err(err);
+        }
+        rx.await.map_err(|_| "Request cancelled".to_string())
+    }
+
+    async fn request_batch(
+        &self,
+        messages: Vec<serde_json::Value>,
+    ) -> Result<Vec<serde_json::Value>, String> {
+        let mut receivers = Vec::new();
+        let mut keys = Vec::new();
+        for message in messages.iter().filter(|message| is_request(message)) {
+            let (tx, rx) = oneshot::channel();
+            match self.register(message, Responder::Json(tx)).await {
+                Ok(key) => keys.push(key),
+                Err(err) => {
+                    for key in &keys {
+                        self.pending.cancel(key).await;
+                    }
+                    return Err(err);
+                }
+            }
+            receivers.push(rx);
+        }
+        for message in &messages {
+            if let Err(err) = self.send(message).await {
+                for key in &keys {
+                    self.pending.cancel(key).await;
+                }
+                return Err(err);
+            }
+        }
+        let mut responses = Vec::with_capacity(receivers.len());
+        for rx in receivers {
+            responses.push(rx.await.map_err(|_| "Request cancelled".to_string())?);
+        }
+        Ok(responses)
+    }
+
+    async fn request_stream(
+        &self,
+        message: serde_json::Value,
+    ) -> Result<mpsc::UnboundedReceiver<serde_json::Value>, String> {
+        let progress_token = message
+            .get("params")
+            .and_then(|params| params.get("_meta"))
+            .and_then(|meta| meta.get("progressToken"))
+            .cloned();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let key = self.register(&message, Responder::Stream(tx.clone())).await?;
+        self.streams.lock().await.push(RequestStream {
+            key: key.clone(),
+            progress_token,
+            tx,
+        });
+        if let Err(err) = self.child.send(&message).await {
+            self.pending.cancel(&key).await;
+            self.streams.lock().await.retain(|stream| stream.key != key);
+            return Err(err);
+        }
+        Ok(rx)
+    }
+
+    // Progress notifications go to the request that owns the token. Anything
+    // else the child emits mid-call can only be tied to a request while a
+    // single streamed request is open; otherwise it goes to the GET stream.
+    async fn send_to_related_stream(&self, msg: &serde_json::Value) -> bool {
+        let mut streams = self.streams.lock().await;
+        streams.retain(|stream| !stream.tx.is_closed());
+        let is_progress =
+            msg.get("method").and_then(|m| m.as_str()) == Some("notifications/progress");
+        let token = msg
+            .get("params")
+            .and_then(|params| params.get("progressToken"));
+        let target = match token {
+            Some(token) if is_progress => streams
+                .iter()
+                .find(|stream| stream.progress_token.as_ref() == Some(token)),
+            _ if streams.len() == 1 => streams.first(),
+            _ => None,
+        };
+        match target {
+            Some(stream) => stream.tx.send(msg.clone()).is_ok(),
+            None => false,
+        }
+    }
+
+    // Answers requests the child never responded to, so the client is not
+    // left waiting forever.
+    async fn expire_pending(&self) {
+        for (key, responder) in self.pending.expire(self.request_timeout).await {
+            let id = key.to_value();
+            tracing::error!("Session {}: request {id} timed out", self.id);
+            let _ = self.abandoned.register(&id, ()).await;
+            let error = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32001, "message": "Timed out waiting for child response" },
+            });
+            match responder {
+                Responder::Json(sender) => {
+                    let _ = sender.send(error);
+                }
+                Responder::Stream(tx) => {
+                    self.streams.lock().await.retain(|stream| stream.key != key);
+                    let _ = tx.send(error);
+                }
+            }
+        }
+        self.abandoned.expire(self.request_timeout).await;
+    }
+
+    async fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.child.shutdown().await;
+        self.client_requests.drain().await;
+        self.pending.drain().await;
+        self.abandoned.drain().await;
+        self.streams.lock().await.clear();
+    }
+
+    async fn restart(&self, runtime: &RuntimeArgs) -> Result<(), String> {
+        self.child.restart(runtime).await
+    }
+}
+
+struct SessionManager {
+    source: SourceSpec,
+    request_timeout: Duration,
+    client_response_timeout: Duration,
+    responder: Arc<LocalResponder>,
+    shared: Option<Arc<SharedChild>>,
+    runtime: RuntimeArgsStore,
+    sessions: Arc<Mutex<HashMap<String, Arc<Session>>>>,
+    session_counter: Option<Arc<SessionAccessCounter>>,
+}
+
+impl SessionManager {
+    fn new(
+        source: SourceSpec,
+        runtime: RuntimeArgsStore,
+        session_timeout: Option<u64>,
+        request_timeout: Duration,
+        client_response_timeout: Duration,
+        responder: Arc<LocalResponder>,
+        shared: Option<Arc<SharedChild>>,
+    ) -> Self {
+        let sessions: Arc<Mutex<HashMap<String, Arc<Session>>>> = Arc::new(Mutex::new(HashMap::new()));
+        let session_counter = session_timeout.map(|timeout| {
+            let sessions_clone = sessions.clone();
+            Arc::new(SessionAccessCounter::new(
+                timeout,
+                Arc::new(move |session_id| {
+                    tracing::info!("Session {session_id} timed out, cleaning up");
+                    let sessions_inner = sessions_clone.clone();
+                    tokio::spawn(async move {
+                        let removed = sessions_inner.lock().await.remove(&session_id);
+                        if let Some(session) = removed {
+                            session.close().await;
+                        }
+                    });
+                }),
+            ))
+        });
+        Self {
+            source,
+            request_timeout,
+            client_response_timeout,
+            responder,
+            shared,
+            runtime,
+            sessions,
+            session_counter,
+        }
+    }
+
+    async fn create_session(&self, principal: Option<Principal>) -> Result<Arc<Session>, String> {
+        let session_id = Uuid::new_v4().to_string();
+        let runtime = self.runtime.get_effective(Some(&session_id)).await;
+        if let Some(principal) = &principal {
+            tracing::info!("Session {session_id} created for {principal}");
+        }
+        let session =
+            Arc::new(
+            Session::new(
+                session_id.clone(),
+                principal,
+                &self.source,
+                runtime,
+                self.responder.clone(),
+                self.request_timeout,
+                self.shared.as_ref(),
+            )
+            .await?,
+        );
+        session.clone().expire_client_requests(self.client_response_timeout);
+        session.clone().start_routing().await;
+        let mut sessions = self.sessions.lock().await;
+        sessions.insert(session_id.clone(), session.clone());
+        if let Some(counter) = &self.session_counter {
+            counter.inc(&session_id, "session initialization").await;
+        }
+        Ok(session)
+    }
+
+    async fn get_session(&self, session_id: &str) -> Option<Arc<Session>> {
+        let sessions = self.sessions.lock().await;
+        sessions.get(session_id).cloned()
+    }
+
+    async fn remove_session(&self, session_id: &str) -> bool {
+        let mut sessions = self.sessions.lock().await;
+        let removed = sessions.remove(session_id);
+        if let Some(session) = removed {
+            session.close().await;
+            true
+        } else {
+            false
+        }
+    }
+
+    async fn session_inc(&self, session_id: &str, reason: &str) {
+        if let Some(counter) = &self.session_counter {
+            counter.inc(session_id, reason).await;
+        }
+    }
+
+    async fn session_dec(&self, session_id: &str, reason: &str) {
+        if let Some(counter) = &self.session_counter {
+            counter.dec(session_id, reason).await;
+        }
+    }
+
+    async fn restart_session(&self, session_id: &str) -> Result<(), String> {
+        let runtime = self.runtime.get_effective(Some(session_id)).await;
+        let sessions = self.sessions.lock().await;
+        if let Some(session) = sessions.get(session_id) {
+            session.restart(&runtime).await?;
+        }
+        Ok(())
+    }
+
+    async fn restart_all(&self) {
+        let ids: Vec<String> = {
+            let sessions = self.sessions.lock().await;
+            sessions.keys().cloned().collect()
+        };
+        for id in ids {
+            let _ = self.restart_session(&id).await;
+        }
+    }
+}
+
+async fn handle_stateless_request(
+    state: &AppState,
+    runtime: RuntimeArgs,
+    payload: serde_json::Value,
+    events: Option<&mpsc::UnboundedSender<serde_json::Value>>,
+) -> Result<Option<serde_json::Value>, String> {
+    let batch = payload.is_array();
+    let messages = match payload {
+        serde_json::Value::Array(items) => items,
+        other => vec![other],
+    };
+    let responses = match (&state.pool, &state.shared) {
+        (Some(pool), _) if !messages.iter().any(is_initialize_request) => {
+            let lease = pool.lease().await?;
+            let result = stateless_exchange(
+                lease.source(),
+                &state.protocol_version,
+                &state.responder,
+                messages,
+                events,
+                true,
+            )
+            .await;
+            lease.release(result.is_ok()).await;
+            result?
+        }
+        // Clients initialize the shared child themselves, as SSE and WS
+        // clients do.
+        (_, Some(shared)) => {
+            let upstream = RequestChild(shared.attach(&Uuid::new_v4().to_string()).await);
+            stateless_exchange(
+                upstream.0.as_ref(),
+                &state.protocol_version,
+                &state.responder,
+                messages,
+                events,
+                true,
+            )
+            .await?
+        }
+        _ => {
+            let upstream = RequestChild(state.source.open(&runtime, false).await?);
+            stateless_exchange(
+                upstream.0.as_ref(),
+                &state.protocol_version,
+                &state.responder,
+                messages,
+                events,
+                false,
+            )
+            .await?
+        }
+    };
+    if !batch {
+        return Ok(responses.into_iter().next());
+    }
+    if responses.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(serde_json::Value::Array(responses)))
+    }
+}
+
+// Shuts a per-request child down once the request is done, including when the
+// client disconnects and the handler is dropped mid-exchange.
+struct RequestChild(Arc<dyn MessageSource>);
+
+impl Drop for RequestChild {
+    fn drop(&mut self) {
+        let child = self.0.clone();
+        tokio::spawn(async move { child.shutdown().await });
+    }
+}
+
+async fn stateless_exchange(
+    upstream: &dyn MessageSource,
+    protocol_version: &str,
+    responder: &LocalResponder,
+    messages: Vec<serde_json::Value>,
+    events: Option<&mpsc::UnboundedSender<serde_json::Value>>,
+    initialized: bool,
+) -> Result<Vec<serde_json::Value>, String> {
+    let mut remaining = request_ids(&messages);
+    if remaining.is_empty() {
+        for message in &messages {
+            upstream.send(message).await?;
+        }
+        return Ok(Vec::new());
+    }
+
+    let mut rx = upstream.subscribe();
+    let mut pending_messages: Option<Vec<serde_json::Value>> = None;
+    let mut init_pending: Option<String> = None;
+
+    if !initialized && !messages.iter().any(is_initialize_request) {
+        let init_id = auto_init_id();
+        upstream
+            .send(&create_initialize_request(
+                &init_id,
+                protocol_version,
+                responder.capabilities(),
+            ))
+            .await?;
+        init_pending = Some(init_id);
+        pending_messages = Some(messages);
+    } else {
+        for message in &messages {
+            upstream.send(message).await?;
+        }
+    }
+
+    let mut responses = Vec::with_capacity(remaining.len());
+    let mut liveness = tokio::time::interval(std::time::Duration::from_millis(250));
+    loop {
+        let msg = tokio::select! {
+            biased;
+            msg = rx.recv() => match msg {
+                Ok(msg) => msg,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            _ = liveness.tick() => {
+                if upstream.is_alive().await {
+                    continue;
+                }
+                match rx.try_recv() {
+                    Ok(msg) => msg,
+                    Err(_) => break,
+                }
+            }
+        };
+        if is_request(&msg) {
+            upstream.send(&responder.respond(&msg).await).await?;
+            continue;
+        }
+        let Some(id) = msg.get("id") else {
+            if let (None, Some(events)) = (&init_pending, events) {
+                let _ = events.send(msg);
+            }
+            continue;
+        };
+        if init_pending.is_some() && id.as_str() == init_pending.as_deref() {
+            upstream.send(&create_initialized_notification()).await?;
+            for message in pending_messages.take().unwrap_or_default() {
+                upstream.send(&message).await?;
+            }
+            init_pending = None;
+            continue;
+        }
+        if let Some(index) = remaining.iter().position(|expected| expected == id) {
+            remaining.remove(index);
+            responses.push(msg);
+            if remaining.is_empty() {
+                return Ok(responses);
+            }
+        }
+    }
+    Err("Child terminated before response".to_string())
+}
@@ -0,0 +1,341 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::{SinkExt, StreamExt};
+use tokio::sync::{broadcast, mpsc, Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+
+use crate::support::backoff::Backoff;
+use crate::support::correlator::RequestId;
+use crate::support::jsonrpc::{
+    create_initialized_notification, is_initialize_request, is_request, is_response,
+};
+use crate::transport::MessageSource;
+use crate::types::{HeadersMap, RuntimeArgs};
+
+const REPLAY_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct WsClient {
+    outgoing: mpsc::Sender<serde_json::Value>,
+    headers: Arc<RwLock<HeadersMap>>,
+    last_initialize: Arc<Mutex<Option<serde_json::Value>>>,
+    sender: broadcast::Sender<serde_json::Value>,
+    connection_task: JoinHandle<()>,
+}
+
+impl WsClient {
+    pub fn connect(ws_url: String, runtime: &RuntimeArgs) -> Arc<Self> {
+        let (sender, _) = broadcast::channel(256);
+        let (outgoing, outgoing_rx) = mpsc::channel::<serde_json::Value>(256);
+        let headers = Arc::new(RwLock::new(runtime.headers.clone()));
+        let last_initialize: Arc<Mutex<Option<serde_json::Value>>> = Arc::new(Mutex::new(None));
+        let connection_task = tokio::spawn(connection_loop(
+            ws_url,
+            headers.clone(),
+            outgoing_rx,
+            last_initialize.clone(),
+            sender.clone(),
+        ));
+        Arc::new(Self {
+            outgoing,
+            headers,
+            last_initialize,
+            sender,
+            connection_task,
+        })
+    }
+
+    pub async fn send(&self, message: &serde_json::Value) -> Result<(), String> {
+        if is_initialize_request(message) {
+            *self.last_initialize.lock().await = Some(message.clone());
+        }
+        self.outgoing
+            .send(message.clone())
+            .await
+            .map_err(|_| "WebSocket connection task stopped".to_string())
+    }
+}
+
+impl MessageSource for WsClient {
+    fn send<'a>(&'a self, message: &'a serde_json::Value) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(WsClient::send(self, message))
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<serde_json::Value> {
+        self.sender.subscribe()
+    }
+
+    fn is_alive(&self) -> BoxFuture<'_, bool> {
+        Box::pin(async move { !self.connection_task.is_finished() })
+    }
+
+    fn restart<'a>(&'a self, runtime: &'a RuntimeArgs) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            *self.headers.write().await = runtime.headers.clone();
+            Ok(())
+        })
+    }
+
+    fn shutdown(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            self.connection_task.abort();
+        })
+    }
+}
+
+async fn connection_loop(
+    ws_url: String,
+    headers: Arc<RwLock<HeadersMap>>,
+    mut outgoing: mpsc::Receiver<serde_json::Value>,
+    last_initialize: Arc<Mutex<Option<serde_json::Value>>>,
+    sender: broadcast::Sender<serde_json::Value>,
+) {
+    let mut outbox = Outbox::default();
+    let mut backoff = Backoff::default();
+    let mut retrying = false;
+
+    loop {
+        if retrying {
+            let delay = backoff.next_delay();
+            tracing::info!("Reconnecting to WebSocket in {}ms", delay.as_millis());
+            if backoff.gave_up() {
+                // The server has been gone too long to keep the client's
+                // messages waiting for it.
+                if let Some(message) = outbox.unsent.take() {
+                    reject(&sender, &message);
+                }
+                let sleep = tokio::time::sleep(delay);
+                tokio::pin!(sleep);
+                loop {
+                    tokio::select! {
+                        _ = &mut sleep => break,
+                        message = outgoing.recv() => match message {
+                            Some(message) => reject(&sender, &message),
+                            None => return,
+                        },
+                    }
+                }
+            } else {
+                tokio::time::sleep(delay).await;
+            }
+        }
+        retrying = true;
+
+        let current_headers = headers.read().await.clone();
+        let request = match build_request(&ws_url, &current_headers) {
+            Ok(request) => request,
+            Err(err) => {
+                tracing::error!("Invalid WebSocket request: {err}");
+                return;
+            }
+        };
+        let socket = match tokio_tungstenite::connect_async(request).await {
+            Ok((socket, _)) => socket,
+            Err(err) => {
+                tracing::error!("WebSocket connection failed: {err}");
+                continue;
+            }
+        };
+        tracing::info!("WebSocket connected: {ws_url}");
+        let (mut sink, mut stream) = socket.split();
+
+        // Client messages are held back until a replayed initialize has been
+        // answered and followed by notifications/initialized.
+        let mut replay_id: Option<String> = None;
+        let replay_deadline = tokio::time::sleep(REPLAY_TIMEOUT);
+        tokio::pin!(replay_deadline);
+        let resending_initialize = outbox.unsent.as_ref().is_some_and(is_initialize_request);
+        if outbox.initialize_sent && !resending_initialize {
+            if let Some(mut init) = last_initialize.lock().await.clone() {
+                let id = format!("reinit_{}", Uuid::new_v4());
+                if let Some(obj) = init.as_object_mut() {
+                    obj.insert("id".to_string(), serde_json::Value::String(id.clone()));
+                }
+                tracing::info!("Replaying initialize after reconnect");
+                if send_json(&mut sink, &init).await.is_err() {
+                    continue;
+                }
+                replay_id = Some(id);
+            }
+        }
+
+        if replay_id.is_none() {
+            backoff.reset();
+            if !outbox.flush(&mut sink).await {
+                continue;
+            }
+        }
+
+        loop {
+            tokio::select! {
+                message = outgoing.recv(), if replay_id.is_none() => {
+                    let Some(message) = message else {
+                        let _ = sink.close().await;
+                        return;
+                    };
+                    if !outbox.deliver(&mut sink, message).await {
+                        break;
+                    }
+                }
+                _ = &mut replay_deadline, if replay_id.is_some() => {
+                    tracing::error!("Replayed initialize timed out, reconnecting...");
+                    break;
+                }
+                incoming = stream.next() => {
+                    match incoming {
+                        Some(Ok(Message::Text(text))) => {
+                            let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) else {
+                                tracing::error!("Invalid JSON from WebSocket: {text}");
+                                continue;
+                            };
+                            let is_replay = replay_id.is_some()
+                                && json.get("method").is_none()
+                                && json.get("id").and_then(|v| v.as_str()) == replay_id.as_deref();
+                            if is_replay {
+                                replay_id = None;
+                                if json.get("error").is_some() {
+                                    tracing::error!("Initialize replay failed: {json}");
+                                } else if send_json(&mut sink, &create_initialized_notification())
+                                    .await
+                                    .is_err()
+                                {
+                                    break;
+                                }
+                                backoff.reset();
+                                if !outbox.flush(&mut sink).await {
+                                    break;
+                                }
+                                continue;
+                            }
+                            if is_response(&json) {
+                                if let Some(id) = json.get("id").and_then(RequestId::from_value) {
+                                    outbox.pending.remove(&id);
+                                }
+                            }
+                            let _ = sender.send(json);
+                        }
+                        Some(Ok(Message::Close(_))) | None => {
+                            tracing::error!("WebSocket closed, reconnecting...");
+                            break;
+                        }
+                        Some(Ok(_)) => {}
+                        Some(Err(err)) => {
+                            tracing::error!("WebSocket error: {err}, reconnecting...");
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        // The new connection will not answer requests sent on this one.
+        for id in outbox.pending.drain() {
+            let _ = sender.send(connection_lost(&id.to_value()));
+        }
+    }
+}
+
+// Client messages as the loop tracks them across connections.
+#[derive(Default)]
+struct Outbox {
+    // A message whose send failed, to go out first on the next connection.
+    unsent: Option<serde_json::Value>,
+    // Once the client's initialize has gone out, every new connection is a
+    // new server session that has to be initialized again.
+    initialize_sent: bool,
+    // Requests sent on the current connection that have no response yet.
+    pending: HashSet<RequestId>,
+}
+
+impl Outbox {
+    async fn flush<S>(&mut self, sink: &mut S) -> bool
+    where
+        S: futures::Sink<Message, Error = tokio_tungstenite::tungstenite::Error> + Unpin,
+    {
+        match self.unsent.take() {
+            Some(message) => self.deliver(sink, message).await,
+            None => true,
+        }
+    }
+
+    // Sends a client message, keeping it for the next connection when the
+    // send fails.
+    async fn deliver<S>(&mut self, sink: &mut S, message: serde_json::Value) -> bool
+    where
+        S: futures::Sink<Message, Error = tokio_tungstenite::tungstenite::Error> + Unpin,
+    {
+        if send_json(sink, &message).await.is_err() {
+            self.unsent = Some(message);
+            return false;
+        }
+        self.initialize_sent |= is_initialize_request(&message);
+        if is_request(&message) {
+            if let Some(id) = message.get("id").and_then(RequestId::from_value) {
+                self.pending.insert(id);
+            }
+        }
+        true
+    }
+}
+
+fn reject(sender: &broadcast::Sender<serde_json::Value>, message: &serde_json::Value) {
+    if !is_request(message) {
+        tracing::warn!("Dropping message while WebSocket is disconnected: {message}");
+        return;
+    }
+    let id = message.get("id").cloned().unwrap_or(serde_json::Value::Null);
+    let _ = sender.send(serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {
+            "code": -32000,
+            "message": "WebSocket connection unavailable",
+        }
+    }));
+}
+
+fn connection_lost(id: &serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {
+            "code": -32000,
+            "message": "WebSocket connection lost before the response arrived",
+        }
+    })
+}
+
+fn build_request(
+    ws_url: &str,
+    headers: &HeadersMap,
+) -> Result<tokio_tungstenite::tungstenite::handshake::client::Request, String> {
+    let mut request = ws_url
+        .into_client_request()
+        .map_err(|err| err.to_string())?;
+    for (k, v) in headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(k.as_bytes()),
+            HeaderValue::from_str(v),
+        ) {
+            request.headers_mut().insert(name, value);
+        }
+    }
+    Ok(request)
+}
+
+async fn send_json<S>(sink: &mut S, message: &serde_json::Value) -> Result<(), String>
+where
+    S: futures::Sink<Message, Error = tokio_tungstenite::tungstenite::Error> + Unpin,
+{
+    let text = serde_json::to_string(message).map_err(|err| err.to_string())?;
+    sink.send(Message::Text(text)).await.map_err(|err| {
+        tracing::error!("WebSocket send failed: {err}");
+        err.to_string()
+    })
+}
@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Extension, Router};
+use futures::future::BoxFuture;
+use futures::{SinkExt, StreamExt};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{mpsc, Mutex};
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::support::auth::Principal;
+use crate::support::headers::ResponseHeaders;
+use crate::runtime::{RuntimeApplyResult, RuntimeUpdateRequest};
+use crate::runtime::store::{RuntimeArgsStore, RuntimeArgsUpdate, UpdateResult};
+use crate::transport::mount::{serve_mounts, Mount, RuntimeTarget};
+use crate::transport::shared_child::{Deliver, SharedChild, Upstream};
+use crate::transport::{MessageSink, MessageSource, SourceSpec};
+
+#[derive(Clone)]
+struct AppState {
+    clients: Arc<Mutex<HashMap<String, mpsc::Sender<serde_json::Value>>>>,
+    // The principal that opened each connection, kept for later policy.
+    owners: Arc<Mutex<HashMap<String, Principal>>>,
+    shared: Option<Arc<SharedChild>>,
+    children: Arc<Mutex<HashMap<String, Arc<dyn MessageSource>>>>,
+    source: SourceSpec,
+    runtime: RuntimeArgsStore,
+    response_headers: ResponseHeaders,
+    ready: Arc<AtomicBool>,
+}
+
+pub struct WsServer {
+    config: Config,
+}
+
+impl WsServer {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+}
+
+impl MessageSink for WsServer {
+    fn serve(
+        self: Box<Self>,
+        source: SourceSpec,
+        runtime: RuntimeArgsStore,
+        updates: mpsc::Receiver<RuntimeUpdateRequest>,
+    ) -> BoxFuture<'static, Result<(), String>> {
+        Box::pin(run(self.config, source, runtime, updates))
+    }
+}
+
+async fn run(
+    config: Config,
+    source: SourceSpec,
+    runtime: RuntimeArgsStore,
+    updates: mpsc::Receiver<RuntimeUpdateRequest>,
+) -> Result<(), String> {
+    tracing::info!("  - port: {}", config.port);
+    tracing::info!("  - {source}");
+
+    let shared = if config.ws_child_per_connection {
+        None
+    } else {
+        Some(SharedChild::open(&config, &source, &runtime).await?)
+    };
+    let mounted = mount(&config, source, runtime.clone(), shared.clone());
+    serve_mounts(&config, vec![mounted], shared, runtime, updates).await
+}
+
+// Mounts the WebSocket route. `shared` is the shared child, or None for a
+// child per connection.
+pub fn mount(
+    config: &Config,
+    source: SourceSpec,
+    runtime: RuntimeArgsStore,
+    shared: Option<Arc<SharedChild>>,
+) -> Mount {
+    tracing::info!("  - wsPath: {}", config.ws_path);
+    if config.ws_child_per_connection {
+        tracing::info!("  - wsChildPerConnection: true");
+    }
+
+    let children: Arc<Mutex<HashMap<String, Arc<dyn MessageSource>>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+
+    let clients: Arc<Mutex<HashMap<String, mpsc::Sender<serde_json::Value>>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+
+    let ready = Arc::new(AtomicBool::new(false));
+    let remote = source.is_remote();
+    let state = AppState {
+        clients: clients.clone(),
+        owners: Arc::new(Mutex::new(HashMap::new())),
+        shared: shared.clone(),
+        children: children.clone(),
+        source,
+        runtime: runtime.clone(),
+        response_headers: ResponseHeaders::new(&config.headers, runtime.clone(), remote),
+        ready: ready.clone(),
+    };
+
+    let router = Router::new()
+        .route(&config.ws_path, get(ws_handler))
+        .with_state(state.clone());
+
+    let runtime_target = Arc::new(WsRuntime {
+        shared: shared.is_some(),
+        remote,
+        clients,
+        children,
+        runtime,
+    });
+
+    ready.store(true, Ordering::SeqCst);
+
+    tracing::info!(
+        "WebSocket endpoint: ws://localhost:{}{}",
+        config.port, config.ws_path
+    );
+
+    Mount {
+        router,
+        health: Arc::new(move || {
+            let state = state.clone();
+            Box::pin(async move { health_handler(state).await.into_response() })
+        }),
+        runtime: runtime_target,
+    }
+}
+
+struct WsRuntime {
+    shared: bool,
+    remote: bool,
+    clients: Arc<Mutex<HashMap<String, mpsc::Sender<serde_json::Value>>>>,
+    children: Arc<Mutex<HashMap<String, Arc<dyn MessageSource>>>>,
+    runtime: RuntimeArgsStore,
+}
+
+impl RuntimeTarget for WsRuntime {
+    fn apply_global<'a>(
+        &'a self,
+        update: &'a UpdateResult,
+    ) -> BoxFuture<'a, Option<RuntimeApplyResult>> {
+        Box::pin(async move {
+            if self.shared || !update.restarts(self.remote) {
+                return None;
+            }
+            let connections: Vec<(String, Arc<dyn MessageSource>)> = self
+                .children
+                .lock()
+                .await
+                .iter()
+                .map(|(id, child)| (id.clone(), child.clone()))
+                .collect();
+            for (id, child) in connections {
+                let args = self.runtime.get_effective(Some(&id)).await;
+                if let Err(err) = child.restart(&args).await {
+                    tracing::error!("Failed to restart child for connection {id}: {err}");
+                }
+            }
+            Some(if update.restart_needed {
+                RuntimeApplyResult::ok("Restarted all connections with new runtime args", true)
+            } else {
+                RuntimeApplyResult::ok("Updated remote server headers for all connections", false)
+            })
+        })
+    }
+
+    fn apply_session<'a>(
+        &'a self,
+        session_id: &'a str,
+        update: RuntimeArgsUpdate,
+    ) -> BoxFuture<'a, Option<RuntimeApplyResult>> {
+        Box::pin(async move {
+            if self.shared {
+                return self.clients.lock().await.contains_key(session_id).then(|| {
+                    RuntimeApplyResult::error(
+                        "Per-session runtime overrides require --wsChildPerConnection for WS output",
+                    )
+                });
+            }
+            let child = self.children.lock().await.get(session_id).cloned()?;
+            let update_result = self.runtime.update_session(session_id, update).await;
+            if !update_result.restarts(self.remote) {
+                return Some(RuntimeApplyResult::ok("Updated session runtime args", false));
+            }
+            let args = self.runtime.get_effective(Some(session_id)).await;
+            Some(match child.restart(&args).await {
+                Err(_) => RuntimeApplyResult::error("Failed to restart session"),
+                Ok(()) if update_result.restart_needed => {
+                    RuntimeApplyResult::ok("Restarted session with new runtime args", true)
+                }
+                Ok(()) => RuntimeApplyResult::ok("Updated remote server headers for session", false),
+            })
+        })
+    }
+}
+
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> impl IntoResponse {
+    let principal = principal.map(|Extension(principal)| principal);
+    ws.on_upgrade(move |socket| handle_socket(socket, state, principal))
+}
+
+// Every message on a socket comes from the principal authenticated at the
+// upgrade, so unlike SSE sessions there is no ownership to check per message.
+async fn handle_socket(stream: WebSocket, state: AppState, principal: Option<Principal>) {
+    let client_id = Uuid::new_v4().to_string();
+    if let Some(principal) = principal {
+        tracing::info!("WebSocket connection {client_id} opened by {principal}");
+        state.owners.lock().await.insert(client_id.clone(), principal);
+    }
+    let (mut sender_ws, mut receiver_ws) = stream.split();
+    let (tx, mut rx) = mpsc::channel::<serde_json::Value>(64);
+
+    let (upstream, forward_task) = match &state.shared {
+        Some(shared) => {
+            let tx = tx.clone();
+            let deliver: Deliver = Arc::new(move |msg| {
+                let tx = tx.clone();
+                Box::pin(async move { tx.send(msg).await.is_ok() })
+            });
+            shared.register(&client_id, deliver).await;
+            (Upstream::Shared(shared.clone(), client_id.clone()), None)
+        }
+        None => match spawn_connection_child(&state, &client_id, tx.clone()).await {
+            Ok((child, forward_task)) => (Upstream::Own(child), Some(forward_task)),
+            Err(err) => {
+                tracing::error!("Failed to start child for connection {client_id}: {err}");
+                state.owners.lock().await.remove(&client_id);
+                state.runtime.remove_session(&client_id).await;
+                let _ = sender_ws.send(Message::Close(None)).await;
+                return;
+            }
+        },
+    };
+
+    {
+        let mut clients = state.clients.lock().await;
+        clients.insert(client_id.clone(), tx);
+    }
+
+    let mut send_task = tokio::spawn(async move {
+        while let Some(msg) = rx.recv().await {
+            if let Ok(text) = serde_json::to_string(&msg) {
+                if sender_ws.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let mut recv_task = tokio::spawn(async move {
+        while let Some(Ok(message)) = receiver_ws.next().await {
+            if let Message::Text(text) = message {
+                if let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) {
+                    let _ = upstream.send(json).await;
+                }
+            }
+        }
+    });
+
+    tokio::select! {
+        _ = &mut send_task => recv_task.abort(),
+        _ = &mut recv_task => send_task.abort(),
+    }
+
+    {
+        let mut clients = state.clients.lock().await;
+        clients.remove(&client_id);
+    }
+    state.owners.lock().await.remove(&client_id);
+
+    if let Some(shared) = &state.shared {
+        shared.unregister(&client_id).await;
+    }
+    if let Some(forward_task) = forward_task {
+        forward_task.abort();
+        let child = state.children.lock().await.remove(&client_id);
+        if let Some(child) = child {
+            tracing::info!("WebSocket connection {client_id} closed, shutting down its child");
+            child.shutdown().await;
+        }
+        state.runtime.remove_session(&client_id).await;
+    }
+}
+
+async fn spawn_connection_child(
+    state: &AppState,
+    client_id: &str,
+    tx: mpsc::Sender<serde_json::Value>,
+) -> Result<(Arc<dyn MessageSource>, tokio::task::JoinHandle<()>), String> {
+    state.runtime.register_session(client_id).await;
+    let args = state.runtime.get_effective(Some(client_id)).await;
+    let child = state.source.open(&args, false).await?;
+    let mut rx = child.subscribe();
+    state
+        .children
+        .lock()
+        .await
+        .insert(client_id.to_string(), child.clone());
+
+    let connection = client_id.to_string();
+    let forward_task = tokio::spawn(async move {
+        loop {
+            let msg = match rx.recv().await {
+                Ok(msg) => msg,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::error!(
+                        "Connection {connection} lagged, dropped {skipped} child messages"
+                    );
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            if tx.send(msg).await.is_err() {
+                break;
+            }
+        }
+    });
+    Ok((child, forward_task))
+}
+
+async fn health_handler(state: AppState) -> impl IntoResponse {
+    let child_alive = match &state.shared {
+        Some(shared) => shared.child().is_alive().await,
+        None => true,
+    };
+    if !child_alive {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Child process has been killed")
+            .into_response();
+    }
+    if !state.ready.load(Ordering::SeqCst) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Server is not ready").into_response();
+    }
+    let mut response = (StatusCode::OK, "ok").into_response();
+    state.response_headers.apply(None, &mut response).await;
+    response
+}
+